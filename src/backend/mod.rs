@@ -1,7 +1,27 @@
+use crate::dns::tsig::TsigKey;
+use hickory_proto::rr::{Name, RecordType};
+use ipnet::IpNet;
 use log::error;
+use rand::seq::SliceRandom;
 use std::collections::HashMap;
-use std::net::IpAddr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
 use std::vec::Vec;
+use update::DynamicRecords;
+use zone::Zone;
+
+pub mod update;
+pub mod zone;
+
+// Name of the name server of the search domain, resolves to the listen ips of
+// the network.
+pub const NAMESERVER_NAME: &str = "ns";
+
+// Length of the short container id, also the minimum length of an id prefix
+// to be resolved so short hex names are not mistaken for ids.
+pub const SHORT_ID_LEN: usize = 12;
 
 // The core structure of the in-memory backing store for the DNS server.
 // TODO: I've initially intermingled v4 and v6 addresses for simplicity; the
@@ -25,8 +45,153 @@ pub struct DNSBackend {
     // Map of network name to bool (network is/is not internal)
     pub network_is_internal: HashMap<String, bool>,
 
-    // search_domain used by aardvark-dns
-    pub search_domain: String,
+    // Map of network name to map of service owner name (_service._proto.name)
+    // to the SRV records published for it.
+    pub service_mappings: HashMap<String, HashMap<String, Vec<SrvRecord>>>,
+
+    // Map of network name to the ips aardvark-dns listens on for it.
+    pub listen_ips: HashMap<String, Vec<IpAddr>>,
+    // Map of network name to the reverse zones (in-addr.arpa/ip6.arpa) covering
+    // its subnets, without trailing dot.
+    pub reverse_zones: HashMap<String, Vec<String>>,
+    // Map of network name to the containers attached to it.
+    pub container_entries: HashMap<String, Vec<ContainerEntry>>,
+    // Map of network name to map of name to the target of its static CNAME
+    // record, fully qualified if it ends with a dot.
+    pub cname_mappings: HashMap<String, HashMap<String, String>>,
+    // Map of network name to map of name to its static TXT records.
+    pub txt_mappings: HashMap<String, HashMap<String, Vec<String>>>,
+    // Map of network name to map of group name (e.g. pod name) to the ids of
+    // its member containers.
+    pub group_mappings: HashMap<String, HashMap<String, Vec<String>>>,
+    // Map of network name to the zones loaded from zone files for it.
+    pub zones: HashMap<String, Vec<Zone>>,
+
+    // Search domains used by aardvark-dns, lowercase without leading and
+    // trailing dot. The first one is used to build fully qualified names.
+    pub search_domains: Vec<String>,
+    // Map of network name to the search domains overriding the global ones.
+    pub network_search_domains: HashMap<String, Vec<String>>,
+    // Serial of the search domain SOA, the time the backend was created.
+    pub serial: u32,
+    // Answer aliases as CNAME to the container name instead of with their own
+    // address records.
+    pub cname_aliases: bool,
+    // Answer reverse lookups with the names of the container on all shared
+    // networks instead of only on the first one.
+    pub ptr_all_aliases: bool,
+    // Answer reverse lookups with the short container id after the names.
+    pub ptr_short_id: bool,
+
+    // Order of address answers for names with several addresses.
+    pub answer_order: AnswerOrder,
+    // Map of network name to the answer order overriding the global one.
+    pub network_answer_order: HashMap<String, AnswerOrder>,
+    // TTL of local answers. Defaults to 0 which means clients should not cache
+    // them, containers can be restarted with a different ip at any time and
+    // the server is local so queries should be fast enough anyway.
+    pub ttl: u32,
+    // Map of network name to the TTL overriding the global one.
+    pub network_ttl: HashMap<String, u32>,
+    // Names resolving to the listen ips of the network the request was made
+    // on, lowercase and without trailing dot, e.g. host.containers.internal.
+    pub gateway_names: Vec<String>,
+    // Subnets besides the host allowed to request zone transfers (AXFR).
+    pub transfer_acl: Vec<IpNet>,
+    // Subnets besides the host allowed to send dynamic updates.
+    pub update_acl: Vec<IpNet>,
+    // Keys accepted for TSIG signed dynamic updates from any address.
+    pub tsig_keys: Vec<TsigKey>,
+    // Records registered with dynamic updates, shared with the backends of
+    // later configuration reloads.
+    pub dynamic_records: Arc<DynamicRecords>,
+    // How queries are forwarded to the upstream servers.
+    pub forward_strategy: ForwardStrategy,
+    // Round-robin position of each answered name, keyed by network, name and
+    // record type.
+    rotations: Mutex<HashMap<String, usize>>,
+}
+
+// Order in which addresses of a name are returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AnswerOrder {
+    // Addresses are returned in configuration order.
+    #[default]
+    Stable,
+    // Every answer starts one address further than the previous one.
+    RoundRobin,
+    // Addresses are returned in random order.
+    Shuffle,
+}
+
+impl FromStr for AnswerOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "stable" => Ok(AnswerOrder::Stable),
+            "round-robin" => Ok(AnswerOrder::RoundRobin),
+            "shuffle" => Ok(AnswerOrder::Shuffle),
+            _ => Err(format!(
+                "invalid answer order {s}, must be one of stable, round-robin or shuffle"
+            )),
+        }
+    }
+}
+
+// How queries are forwarded to several upstream servers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ForwardStrategy {
+    // Servers are tried one after another, each gets an equal share of the
+    // timeout.
+    #[default]
+    Sequential,
+    // Servers are queried one after another without waiting for the previous
+    // ones to fail, the first valid answer wins.
+    Staggered,
+    // All servers are queried at once, the first valid answer wins.
+    Parallel,
+}
+
+impl FromStr for ForwardStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sequential" => Ok(ForwardStrategy::Sequential),
+            "staggered" => Ok(ForwardStrategy::Staggered),
+            "parallel" => Ok(ForwardStrategy::Parallel),
+            _ => Err(format!(
+                "invalid forward strategy {s}, must be one of sequential, staggered or parallel"
+            )),
+        }
+    }
+}
+
+// Metadata of a single container on a network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContainerEntry {
+    pub id: String,
+    pub aliases: Vec<String>,
+    pub ips: Vec<IpAddr>,
+}
+
+// A single MX record pointing to a container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MxRecord {
+    pub preference: u16,
+    // Container name of the mail exchanger, without search domain.
+    pub exchange: String,
+}
+
+// A single SRV record target published by a container.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SrvRecord {
+    pub priority: u16,
+    pub weight: u16,
+    pub port: u16,
+    // Container name the service points to, without search domain.
+    pub target: String,
 }
 
 impl DNSBackend {
@@ -38,14 +203,8 @@ impl DNSBackend {
         ctr_dns_server: HashMap<IpAddr, Option<Vec<IpAddr>>>,
         network_dns_server: HashMap<String, Vec<IpAddr>>,
         network_is_internal: HashMap<String, bool>,
-        mut search_domain: String,
+        search_domains: Vec<String>,
     ) -> DNSBackend {
-        // dns request always end with dot so append one for easier compare later
-        if let Some(c) = search_domain.chars().rev().nth(0) {
-            if c != '.' {
-                search_domain.push('.')
-            }
-        }
         DNSBackend {
             ip_mappings: containers,
             name_mappings: networks,
@@ -53,48 +212,234 @@ impl DNSBackend {
             ctr_dns_server,
             network_dns_server,
             network_is_internal,
-            search_domain,
+            service_mappings: HashMap::new(),
+            listen_ips: HashMap::new(),
+            reverse_zones: HashMap::new(),
+            container_entries: HashMap::new(),
+            group_mappings: HashMap::new(),
+            zones: HashMap::new(),
+            cname_mappings: HashMap::new(),
+            txt_mappings: HashMap::new(),
+            search_domains: normalize_search_domains(&search_domains),
+            network_search_domains: HashMap::new(),
+            serial: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_secs() as u32)
+                .unwrap_or(1),
+            cname_aliases: false,
+            ptr_all_aliases: false,
+            ptr_short_id: false,
+            answer_order: AnswerOrder::default(),
+            network_answer_order: HashMap::new(),
+            ttl: 0,
+            network_ttl: HashMap::new(),
+            gateway_names: Vec::new(),
+            transfer_acl: Vec::new(),
+            update_acl: Vec::new(),
+            tsig_keys: Vec::new(),
+            dynamic_records: Arc::new(DynamicRecords::default()),
+            forward_strategy: ForwardStrategy::default(),
+            rotations: Mutex::new(HashMap::new()),
         }
     }
 
-    // Handle a single DNS lookup made by a given IP.
-    // Returns all the ips for the given entry name
-    pub fn lookup(
-        &self,
-        requester: &IpAddr,
-        network_name: &str,
-        entry: &str,
-    ) -> Option<Vec<IpAddr>> {
-        // Normalize lookup entry to lowercase.
-        let mut name = entry.to_lowercase();
+    // Returns the TTL of local answers on the given network.
+    pub fn ttl(&self, network_name: &str) -> u32 {
+        self.network_ttl
+            .get(network_name)
+            .copied()
+            .unwrap_or(self.ttl)
+    }
+
+    // Order the addresses answered for a name on the given network according
+    // to the network's answer order.
+    pub fn order_answers(&self, network_name: &str, key: &str, addrs: &mut [IpAddr]) {
+        if addrs.len() < 2 {
+            return;
+        }
+        let order = self
+            .network_answer_order
+            .get(network_name)
+            .copied()
+            .unwrap_or(self.answer_order);
+        match order {
+            AnswerOrder::Stable => {}
+            AnswerOrder::RoundRobin => {
+                let mut rotations = match self.rotations.lock() {
+                    Ok(r) => r,
+                    Err(e) => e.into_inner(),
+                };
+                let position = rotations
+                    .entry(format!("{network_name}/{key}"))
+                    .or_default();
+                addrs.rotate_left(*position % addrs.len());
+                *position = position.wrapping_add(1);
+            }
+            AnswerOrder::Shuffle => addrs.shuffle(&mut rand::rng()),
+        }
+    }
+
+    // Returns the search domains of the given network.
+    pub fn search_domains(&self, network_name: &str) -> &[String] {
+        self.network_search_domains
+            .get(network_name)
+            .unwrap_or(&self.search_domains)
+    }
+
+    // Returns the search domain of the network which is the given name or
+    // contains it, the most specific one if several match.
+    fn matching_search_domain(&self, network_name: &str, entry: &str) -> Option<&str> {
+        let name = entry.trim_end_matches('.').to_lowercase();
+        self.search_domains(network_name)
+            .iter()
+            .filter(|domain| {
+                name == **domain
+                    || name
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|n| n.ends_with('.'))
+            })
+            .max_by_key(|domain| domain.len())
+            .map(|domain| domain.as_str())
+    }
+
+    // Checks if the given name is one of the search domains of the network.
+    pub fn is_search_domain(&self, network_name: &str, entry: &str) -> bool {
+        self.matching_search_domain(network_name, entry)
+            .is_some_and(|domain| entry.trim_end_matches('.').eq_ignore_ascii_case(domain))
+    }
+
+    // Checks if the given name is a search domain of the network or a name
+    // below it.
+    pub fn in_search_domain(&self, network_name: &str, entry: &str) -> bool {
+        self.matching_search_domain(network_name, entry).is_some()
+    }
+
+    // Returns the zone aardvark-dns is authoritative for which contains the
+    // given name, either a search domain or one of the reverse zones of the
+    // network.
+    pub fn authoritative_zone(&self, network_name: &str, entry: &str) -> Option<String> {
+        if let Some(domain) = self.matching_search_domain(network_name, entry) {
+            return Some(domain.to_string());
+        }
+
+        let name = entry.trim_end_matches('.').to_lowercase();
+        self.reverse_zones
+            .get(network_name)?
+            .iter()
+            .find(|zone| name == **zone || name.ends_with(&format!(".{zone}")))
+            .cloned()
+    }
 
-        // Trim off configured search domain if needed as keys do not contain it.
-        // There doesn't seem to be a nicer way to do that:
-        // https://users.rust-lang.org/t/can-strip-suffix-mutate-a-string-value/86852
-        if name.ends_with(&self.search_domain) {
-            name.truncate(name.len() - self.search_domain.len())
+    // Returns the zone file zone of the network containing the name, the most
+    // specific one if zones are nested.
+    pub fn lookup_zone(&self, network_name: &str, name: &Name) -> Option<&Zone> {
+        self.zones
+            .get(network_name)?
+            .iter()
+            .filter(|zone| zone.contains(name))
+            .max_by_key(|zone| zone.origin.num_labels())
+    }
+
+    // Checks if the given name has any records visible to the requester.
+    pub fn name_exists(&self, requester: &IpAddr, network_name: &str, entry: &str) -> bool {
+        if let Some(zone) = self.authoritative_zone(network_name, entry) {
+            if entry.trim_end_matches('.').eq_ignore_ascii_case(&zone) {
+                return true;
+            }
         }
+        if let Some(ip) = reverse_name_to_ip(entry) {
+            return self.reverse_lookup(requester, &ip).is_some();
+        }
+        let nets = self.requester_networks(requester, network_name);
+        self.lookup(requester, network_name, entry).is_some()
+            || self.has_names_below(&nets, &self.normalize_name(network_name, entry))
+            || self.lookup_srv(requester, network_name, entry).is_some()
+            || self.lookup_cname(requester, network_name, entry).is_some()
+            || self.lookup_txt(requester, network_name, entry).is_some()
+    }
+
+    // Normalize a requested name so it can be used as key for the name maps:
+    // lowercase it and trim the search domain of the network and trailing dot.
+    fn normalize_name(&self, network_name: &str, entry: &str) -> String {
+        let mut name = entry.to_lowercase();
 
         // if this is a fully qualified name, remove dots so backend can perform search
         if name.ends_with(".") {
             name.truncate(name.len() - 1)
         }
 
-        let owned_netns: Vec<String>;
-
-        let nets = match self.ip_mappings.get(requester) {
-            Some(n) => n,
-            // no source ip found let's just allow access to the current network where the request was made
-            // On newer rust versions in CI we can return &vec![network_name.to_string()] directly without the extra assignment to the outer scope
-            None => {
-                owned_netns = vec![network_name.to_string()];
-                &owned_netns
+        // Trim off the search domain if needed as keys do not contain it.
+        if let Some(domain) = self.matching_search_domain(network_name, &name) {
+            if name.len() > domain.len() {
+                name.truncate(name.len() - domain.len() - 1)
             }
-        };
+        }
+
+        name
+    }
+
+    // Returns the key of the dynamic records of a requested name: the fully
+    // qualified name without trailing dot, names outside of the search domains
+    // are qualified with the first search domain of the network.
+    fn dynamic_name(&self, network_name: &str, entry: &str) -> String {
+        let name = entry.trim_end_matches('.').to_lowercase();
+        if self.matching_search_domain(network_name, &name).is_some() {
+            return name;
+        }
+        self.fqdn(network_name, &name)
+            .trim_end_matches('.')
+            .to_string()
+    }
+
+    // Returns the TTL of the records of the given type of a name, the TTL given
+    // with DNS UPDATE for dynamic records, otherwise the TTL of the network.
+    pub fn record_ttl(
+        &self,
+        requester: &IpAddr,
+        network_name: &str,
+        entry: &str,
+        record_type: RecordType,
+    ) -> u32 {
+        let name = self.dynamic_name(network_name, entry);
+        self.requester_networks(requester, network_name)
+            .iter()
+            .find_map(|n| self.dynamic_records.get(n, &name)?.ttl(record_type))
+            .unwrap_or_else(|| self.ttl(network_name))
+    }
+
+    // Returns the networks a requester is allowed to query. If the requester is
+    // unknown only the network where the request was made is used.
+    fn requester_networks(&self, requester: &IpAddr, network_name: &str) -> Vec<String> {
+        match self.ip_mappings.get(requester) {
+            Some(n) => n.clone(),
+            None => vec![network_name.to_string()],
+        }
+    }
+
+    // Build the fully qualified name for a backend name by appending the
+    // first search domain of the network.
+    pub fn fqdn(&self, network_name: &str, name: &str) -> String {
+        match self.search_domains(network_name).first() {
+            Some(domain) => format!("{name}.{domain}."),
+            None => format!("{name}."),
+        }
+    }
+
+    // Handle a single DNS lookup made by a given IP.
+    // Returns all the ips for the given entry name
+    pub fn lookup(
+        &self,
+        requester: &IpAddr,
+        network_name: &str,
+        entry: &str,
+    ) -> Option<Vec<IpAddr>> {
+        let name = self.normalize_name(network_name, entry);
+        let dynamic_name = self.dynamic_name(network_name, entry);
+        let nets = self.requester_networks(requester, network_name);
 
         let mut results: Vec<IpAddr> = Vec::new();
 
-        for net in nets {
+        for net in &nets {
             let net_names = match self.name_mappings.get(net) {
                 Some(n) => n,
                 None => {
@@ -106,6 +451,65 @@ impl DNSBackend {
             if let Some(addrs) = net_names.get(&name) {
                 results.append(&mut addrs.clone());
             }
+            if let Some(dynamic) = self.dynamic_records.get(net, &dynamic_name) {
+                results.extend(dynamic.addrs);
+            }
+        }
+
+        // Group names resolve to the addresses of all members.
+        if results.is_empty() {
+            results = self.lookup_group(&nets, &name);
+        }
+
+        // Container ids and unique id prefixes resolve to the container.
+        if results.is_empty() {
+            results = self.lookup_id(&nets, &name);
+        }
+
+        // Names qualified with a network name (<name>.<network>) only resolve
+        // on that network, which must be one of the requester's networks.
+        if results.is_empty() {
+            for net in &nets {
+                let suffix = format!(".{}", net.to_lowercase());
+                let short_name = match name.strip_suffix(&suffix) {
+                    Some(n) if !n.is_empty() => n,
+                    _ => continue,
+                };
+                let only_net = std::slice::from_ref(net);
+                results = match self.name_mappings.get(net).and_then(|m| m.get(short_name)) {
+                    Some(addrs) => addrs.clone(),
+                    None => {
+                        let mut addrs = self.lookup_group(only_net, short_name);
+                        if addrs.is_empty() {
+                            addrs = self.lookup_id(only_net, short_name);
+                        }
+                        if addrs.is_empty() {
+                            addrs = self.lookup_wildcard(only_net, short_name);
+                        }
+                        addrs
+                    }
+                };
+                if !results.is_empty() {
+                    break;
+                }
+            }
+        }
+
+        // The gateway names and the name server of the search domain resolve to
+        // the listen ips of the network the request was made on unless a
+        // container uses that name.
+        if results.is_empty()
+            && (self.gateway_names.contains(&name)
+                || (name == NAMESERVER_NAME && !self.search_domains(network_name).is_empty()))
+        {
+            if let Some(ips) = self.listen_ips.get(network_name) {
+                results.extend_from_slice(ips);
+            }
+        }
+
+        // Exact names take priority, only use wildcards if nothing matched.
+        if results.is_empty() {
+            results = self.lookup_wildcard(&nets, &name);
         }
 
         if results.is_empty() {
@@ -115,6 +519,375 @@ impl DNSBackend {
         Some(results)
     }
 
+    // Resolve a group name to the addresses of its members on the given networks.
+    fn lookup_group(&self, nets: &[String], name: &str) -> Vec<IpAddr> {
+        let mut results: Vec<IpAddr> = Vec::new();
+        for net in nets {
+            let members = match self.group_mappings.get(net).and_then(|g| g.get(name)) {
+                Some(m) => m,
+                None => continue,
+            };
+            for ctr in self.container_entries.get(net).into_iter().flatten() {
+                if members.contains(&ctr.id) {
+                    results.extend_from_slice(&ctr.ips);
+                }
+            }
+        }
+
+        results
+    }
+
+    // Resolve a container id or an id prefix of at least the short id length to
+    // the addresses of the container. Prefixes matching several containers are
+    // not resolved.
+    fn lookup_id(&self, nets: &[String], name: &str) -> Vec<IpAddr> {
+        if name.len() < SHORT_ID_LEN || !name.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Vec::new();
+        }
+
+        let mut id: Option<&str> = None;
+        let mut results: Vec<IpAddr> = Vec::new();
+        for ctr in nets
+            .iter()
+            .filter_map(|n| self.container_entries.get(n))
+            .flatten()
+            .filter(|c| c.id.starts_with(name))
+        {
+            match id {
+                Some(i) if i != ctr.id => return Vec::new(),
+                _ => id = Some(&ctr.id),
+            }
+            results.extend_from_slice(&ctr.ips);
+        }
+
+        results
+    }
+
+    // Resolve a name via wildcard aliases following RFC 4592: the wildcard of
+    // the closest existing ancestor of the name is used, if there is one. A
+    // name with entries below it exists without records (an empty
+    // non-terminal) and is never matched by a wildcard.
+    fn lookup_wildcard(&self, nets: &[String], name: &str) -> Vec<IpAddr> {
+        let maps: Vec<&HashMap<String, Vec<IpAddr>>> = nets
+            .iter()
+            .filter_map(|n| self.name_mappings.get(n))
+            .collect();
+        if !maps.iter().any(|m| m.keys().any(|k| k.starts_with("*."))) {
+            return Vec::new();
+        }
+        if self.has_names_below(nets, name) {
+            return Vec::new();
+        }
+
+        let mut ancestor = name;
+        while let Some((_, parent)) = ancestor.split_once('.') {
+            // A name exists if it has an entry or any entry below it.
+            let suffix = format!(".{parent}");
+            let exists = maps
+                .iter()
+                .any(|m| m.keys().any(|k| k == parent || k.ends_with(&suffix)));
+            if exists {
+                let wildcard = format!("*.{parent}");
+                return maps
+                    .iter()
+                    .filter_map(|m| m.get(&wildcard))
+                    .flatten()
+                    .copied()
+                    .collect();
+            }
+            ancestor = parent;
+        }
+
+        Vec::new()
+    }
+
+    // Whether any name on the networks is below the given one, which then
+    // exists even without records of its own.
+    fn has_names_below(&self, nets: &[String], name: &str) -> bool {
+        let suffix = format!(".{name}");
+        nets.iter()
+            .filter_map(|n| self.name_mappings.get(n))
+            .any(|m| m.keys().any(|k| k.ends_with(&suffix)))
+    }
+
+    // Returns the canonical container name if the entry is an alias and
+    // aliases are answered as CNAME. Only unambiguous aliases, i.e. aliases
+    // which point to a single container name and are not a container name
+    // themselves, are returned.
+    pub fn lookup_canonical(
+        &self,
+        requester: &IpAddr,
+        network_name: &str,
+        entry: &str,
+    ) -> Option<String> {
+        if !self.cname_aliases {
+            return None;
+        }
+
+        let name = self.normalize_name(network_name, entry);
+        let nets = self.requester_networks(requester, network_name);
+
+        let mut canonical: Option<&String> = None;
+        for ctrs in nets.iter().filter_map(|n| self.container_entries.get(n)) {
+            for ctr in ctrs.iter().filter(|c| c.aliases.contains(&name)) {
+                let ctr_name = &ctr.aliases[0];
+                if *ctr_name == name {
+                    // This is a container name.
+                    return None;
+                }
+                match canonical {
+                    Some(c) if c != ctr_name => return None,
+                    _ => canonical = Some(ctr_name),
+                }
+            }
+        }
+
+        canonical.cloned()
+    }
+
+    // Handle a single SRV lookup made by a given IP.
+    // Returns all SRV records for the given _service._proto.name entry.
+    pub fn lookup_srv(
+        &self,
+        requester: &IpAddr,
+        network_name: &str,
+        entry: &str,
+    ) -> Option<Vec<SrvRecord>> {
+        let name = self.normalize_name(network_name, entry);
+        let nets = self.requester_networks(requester, network_name);
+
+        let mut results: Vec<SrvRecord> = Vec::new();
+
+        for net in &nets {
+            if let Some(records) = self
+                .service_mappings
+                .get(net)
+                .and_then(|services| services.get(&name))
+            {
+                for record in records {
+                    if !results.contains(record) {
+                        results.push(record.clone());
+                    }
+                }
+            }
+        }
+
+        if results.is_empty() {
+            return None;
+        }
+
+        Some(results)
+    }
+
+    // Handle a single MX lookup made by a given IP.
+    // A container name is its own mail exchanger. The search domain itself
+    // lists all containers publishing an smtp service, this may be empty
+    // but is still answered locally.
+    pub fn lookup_mx(
+        &self,
+        requester: &IpAddr,
+        network_name: &str,
+        entry: &str,
+    ) -> Option<Vec<MxRecord>> {
+        if self.is_search_domain(network_name, entry) {
+            let nets = self.requester_networks(requester, network_name);
+            let mut results: Vec<MxRecord> = Vec::new();
+            for services in nets.iter().filter_map(|n| self.service_mappings.get(n)) {
+                for (name, records) in services {
+                    if !name.starts_with("_smtp._tcp.") {
+                        continue;
+                    }
+                    for record in records {
+                        let mx = MxRecord {
+                            preference: record.priority,
+                            exchange: record.target.clone(),
+                        };
+                        if !results.contains(&mx) {
+                            results.push(mx);
+                        }
+                    }
+                }
+            }
+            results.sort_by(|a, b| {
+                a.preference
+                    .cmp(&b.preference)
+                    .then_with(|| a.exchange.cmp(&b.exchange))
+            });
+            return Some(results);
+        }
+
+        self.lookup(requester, network_name, entry)?;
+        Some(vec![MxRecord {
+            preference: 10,
+            exchange: self.normalize_name(network_name, entry),
+        }])
+    }
+
+    // Handle a single container metadata lookup made by a given IP.
+    // Returns the network name and container entry for every container with
+    // the given name on the networks of the requester.
+    pub fn lookup_containers(
+        &self,
+        requester: &IpAddr,
+        network_name: &str,
+        entry: &str,
+    ) -> Option<Vec<(String, &ContainerEntry)>> {
+        let name = self.normalize_name(network_name, entry);
+        let nets = self.requester_networks(requester, network_name);
+
+        let mut results = Vec::new();
+        for net in nets {
+            if let Some(ctrs) = self.container_entries.get(&net) {
+                for ctr in ctrs.iter().filter(|c| c.aliases.contains(&name)) {
+                    results.push((net.clone(), ctr));
+                }
+            }
+        }
+
+        if results.is_empty() {
+            return None;
+        }
+
+        Some(results)
+    }
+
+    // Handle a single static CNAME lookup made by a given IP.
+    // Returns the fully qualified target of the CNAME record of the name.
+    pub fn lookup_cname(
+        &self,
+        requester: &IpAddr,
+        network_name: &str,
+        entry: &str,
+    ) -> Option<String> {
+        let name = self.normalize_name(network_name, entry);
+        let nets = self.requester_networks(requester, network_name);
+
+        let target = match nets
+            .iter()
+            .filter_map(|n| self.cname_mappings.get(n))
+            .find_map(|cnames| cnames.get(&name))
+        {
+            Some(target) => target.clone(),
+            None => {
+                let dynamic_name = self.dynamic_name(network_name, entry);
+                nets.iter()
+                    .find_map(|n| self.dynamic_records.get(n, &dynamic_name)?.cname)?
+            }
+        };
+        if target.ends_with('.') {
+            Some(target)
+        } else {
+            Some(self.fqdn(network_name, &target))
+        }
+    }
+
+    // Handle a single static TXT lookup made by a given IP.
+    // Returns the TXT records of the name on the networks of the requester.
+    pub fn lookup_txt(
+        &self,
+        requester: &IpAddr,
+        network_name: &str,
+        entry: &str,
+    ) -> Option<Vec<String>> {
+        let name = self.normalize_name(network_name, entry);
+        let nets = self.requester_networks(requester, network_name);
+
+        let mut results: Vec<String> = Vec::new();
+        for txts in nets
+            .iter()
+            .filter_map(|n| self.txt_mappings.get(n))
+            .filter_map(|t| t.get(&name))
+        {
+            for txt in txts {
+                if !results.contains(txt) {
+                    results.push(txt.clone());
+                }
+            }
+        }
+        let dynamic_name = self.dynamic_name(network_name, entry);
+        for dynamic in nets
+            .iter()
+            .filter_map(|n| self.dynamic_records.get(n, &dynamic_name))
+        {
+            for txt in dynamic.txts {
+                if !results.contains(&txt) {
+                    results.push(txt);
+                }
+            }
+        }
+
+        if results.is_empty() {
+            return None;
+        }
+
+        Some(results)
+    }
+
+    // Returns all names of the search domain zone visible to the requester,
+    // sorted and without search domain. This includes names which only have SRV, CNAME
+    // or TXT records and the name server.
+    pub fn zone_names(&self, requester: &IpAddr, network_name: &str, zone: &str) -> Vec<String> {
+        let nets = self.requester_networks(requester, network_name);
+
+        let mut results: Vec<String> = vec![NAMESERVER_NAME.to_string()];
+        for net in &nets {
+            if let Some(names) = self.name_mappings.get(net) {
+                results.extend(names.keys().cloned());
+            }
+            if let Some(groups) = self.group_mappings.get(net) {
+                results.extend(groups.keys().cloned());
+            }
+            if let Some(cnames) = self.cname_mappings.get(net) {
+                results.extend(cnames.keys().cloned());
+            }
+            if let Some(txts) = self.txt_mappings.get(net) {
+                results.extend(txts.keys().cloned());
+            }
+            if let Some(services) = self.service_mappings.get(net) {
+                results.extend(services.keys().cloned());
+            }
+            results.extend(self.dynamic_records.names(net, zone));
+        }
+        results.sort();
+        results.dedup();
+
+        results
+    }
+
+    // Checks if the requester may request zone transfers. The host, which uses
+    // the listen ips of the networks, is always allowed.
+    pub fn transfer_allowed(&self, requester: &IpAddr) -> bool {
+        self.is_host(requester) || self.transfer_acl.iter().any(|net| net.contains(requester))
+    }
+
+    // Checks if the requester may send unsigned dynamic updates, the host is
+    // always allowed.
+    pub fn update_allowed(&self, requester: &IpAddr) -> bool {
+        self.is_host(requester) || self.update_acl.iter().any(|net| net.contains(requester))
+    }
+
+    // The host uses the listen ips of the networks.
+    fn is_host(&self, requester: &IpAddr) -> bool {
+        requester.is_loopback() || self.listen_ips.values().flatten().any(|ip| ip == requester)
+    }
+
+    // Returns the DNS servers of all containers and networks.
+    pub fn custom_dns_servers(&self) -> Vec<IpAddr> {
+        let mut servers: Vec<IpAddr> = Vec::new();
+        for server in self
+            .ctr_dns_server
+            .values()
+            .flatten()
+            .chain(self.network_dns_server.values())
+            .flatten()
+        {
+            if !servers.contains(server) {
+                servers.push(*server);
+            }
+        }
+        servers
+    }
+
     // Returns list of network resolvers for a particular container
     pub fn get_network_scoped_resolvers(&self, requester: &IpAddr) -> Option<Vec<IpAddr>> {
         let mut results: Vec<IpAddr> = Vec::new();
@@ -161,16 +934,148 @@ impl DNSBackend {
         true
     }
 
-    /// Return a single name resolved via mapping if it exists.
-    pub fn reverse_lookup(&self, requester: &IpAddr, lookup_ip: &IpAddr) -> Option<&Vec<String>> {
+    // Returns the short id of the container with the given ip on the networks of
+    // the requester.
+    pub fn reverse_lookup_id(&self, requester: &IpAddr, lookup_ip: &IpAddr) -> Option<String> {
+        let nets = self.ip_mappings.get(requester)?;
+
+        nets.iter()
+            .filter_map(|n| self.container_entries.get(n))
+            .flatten()
+            .find(|c| c.ips.contains(lookup_ip))
+            .map(|c| c.id.chars().take(SHORT_ID_LEN).collect())
+    }
+
+    // Returns the names of the given ip on the first network shared with the
+    // requester, the container name comes first.
+    pub fn reverse_lookup_first(
+        &self,
+        requester: &IpAddr,
+        lookup_ip: &IpAddr,
+    ) -> Option<Vec<String>> {
         let nets = self.ip_mappings.get(requester)?;
 
         for ips in nets.iter().filter_map(|v| self.reverse_mappings.get(v)) {
             if let Some(names) = ips.get(lookup_ip) {
-                return Some(names);
+                let mut results: Vec<String> = Vec::new();
+                for name in names {
+                    if !results.contains(name) {
+                        results.push(name.clone());
+                    }
+                }
+                return Some(results);
             }
         }
 
         None
     }
+
+    // Returns the unique names of the given ip on all networks shared with the
+    // requester, the container name on the first shared network comes first.
+    pub fn reverse_lookup(&self, requester: &IpAddr, lookup_ip: &IpAddr) -> Option<Vec<String>> {
+        let nets = self.ip_mappings.get(requester)?;
+
+        let mut results: Vec<String> = Vec::new();
+        for names in nets
+            .iter()
+            .filter_map(|n| self.reverse_mappings.get(n))
+            .filter_map(|ips| ips.get(lookup_ip))
+        {
+            for name in names {
+                if !results.contains(name) {
+                    results.push(name.clone());
+                }
+            }
+        }
+
+        if results.is_empty() {
+            return None;
+        }
+
+        Some(results)
+    }
+}
+
+// Normalize search domains: lowercase without leading and trailing dot, empty
+// and duplicate domains are removed.
+pub fn normalize_search_domains(domains: &[String]) -> Vec<String> {
+    let mut result: Vec<String> = Vec::new();
+    for domain in domains {
+        let domain = domain.trim_matches('.').to_lowercase();
+        if !domain.is_empty() && !result.contains(&domain) {
+            result.push(domain);
+        }
+    }
+    result
+}
+
+// Parse the ip address from a reverse lookup name,
+// e.g. 2.0.88.10.in-addr.arpa. or the nibble format in ip6.arpa.
+pub fn reverse_name_to_ip(name: &str) -> Option<IpAddr> {
+    let name = name.to_lowercase();
+    let name = name.trim_end_matches('.');
+
+    if let Some(n) = name.strip_suffix(".in-addr.arpa") {
+        let ip: Ipv4Addr = n
+            .split('.')
+            .rev()
+            .collect::<Vec<&str>>()
+            .join(".")
+            .parse()
+            .ok()?;
+        return Some(IpAddr::V4(ip));
+    }
+
+    let n = name.strip_suffix(".ip6.arpa")?;
+    // ipv6 string is 39 chars max
+    let mut tmp_ip = String::with_capacity(40);
+    for (i, c) in n.split('.').rev().enumerate() {
+        tmp_ip.push_str(c);
+        // insert colon after 4 hex chars but not at the end
+        if i % 4 == 3 && i < 31 {
+            tmp_ip.push(':');
+        }
+    }
+    let ip: Ipv6Addr = tmp_ip.parse().ok()?;
+    Some(IpAddr::V6(ip))
+}
+
+// Returns the reverse zones covering the subnet. Reverse zones end on octet
+// (IPv4) or nibble (IPv6) boundaries, a subnet between them is covered by the
+// zones of the next boundary, e.g. the 16 /24 zones of a /20 or a zone per
+// address of a /28.
+pub fn subnet_reverse_zones(subnet: &IpNet) -> Vec<String> {
+    let step = match subnet {
+        IpNet::V4(_) => 8,
+        IpNet::V6(_) => 4,
+    };
+    let prefix_len = subnet.prefix_len().div_ceil(step) * step;
+    let nets = match subnet.trunc().subnets(prefix_len) {
+        Ok(nets) => nets,
+        Err(_) => return Vec::new(),
+    };
+    nets.map(|net| match net.network() {
+        IpAddr::V4(ipv4) => {
+            let mut labels: Vec<String> = ipv4.octets()[..prefix_len as usize / 8]
+                .iter()
+                .rev()
+                .map(|o| o.to_string())
+                .collect();
+            labels.push("in-addr.arpa".to_string());
+            labels.join(".")
+        }
+        IpAddr::V6(ipv6) => {
+            let mut labels: Vec<String> = ipv6
+                .octets()
+                .iter()
+                .flat_map(|byte| [byte >> 4, byte & 0xf])
+                .take(prefix_len as usize / 4)
+                .map(|nibble| format!("{nibble:x}"))
+                .collect();
+            labels.reverse();
+            labels.push("ip6.arpa".to_string());
+            labels.join(".")
+        }
+    })
+    .collect()
 }
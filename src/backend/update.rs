@@ -0,0 +1,421 @@
+use super::DNSBackend;
+use hickory_proto::op::ResponseCode;
+use hickory_proto::rr::{DNSClass, Name, RData, Record, RecordData, RecordType};
+use log::debug;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Mutex, RwLock};
+
+// Limits of the dynamic records of a network, updates exceeding them are
+// refused.
+const MAX_DYNAMIC_NAMES: usize = 1000;
+const MAX_DYNAMIC_RECORDS: usize = 10000;
+
+// Records of a single name registered with DNS UPDATE.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DynamicName {
+    pub addrs: Vec<IpAddr>,
+    // Fully qualified CNAME target with trailing dot.
+    pub cname: Option<String>,
+    pub txts: Vec<String>,
+    // TTL of each RRset, the one of the last added record.
+    ttls: HashMap<RecordType, u32>,
+}
+
+impl DynamicName {
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Number of records of the name.
+    fn len(&self) -> usize {
+        self.addrs.len() + usize::from(self.cname.is_some()) + self.txts.len()
+    }
+
+    // Returns the TTL of the records of the given type if the name has any.
+    pub fn ttl(&self, record_type: RecordType) -> Option<u32> {
+        let exists = match record_type {
+            RecordType::A => self.addrs.iter().any(|ip| ip.is_ipv4()),
+            RecordType::AAAA => self.addrs.iter().any(|ip| ip.is_ipv6()),
+            RecordType::CNAME => self.cname.is_some(),
+            RecordType::TXT => !self.txts.is_empty(),
+            _ => false,
+        };
+        if !exists {
+            return None;
+        }
+        self.ttls.get(&record_type).copied()
+    }
+}
+
+// Records registered at runtime with DNS UPDATE (RFC 2136). This is an overlay
+// on top of the configuration which is kept when the configuration is reloaded.
+#[derive(Debug, Default)]
+pub struct DynamicRecords {
+    // Map of network name to map of fully qualified name (without trailing
+    // dot) to records. Names are kept per zone so a name added to one search
+    // domain does not show up in the others.
+    names: RwLock<HashMap<String, HashMap<String, DynamicName>>>,
+    // Serializes updates so prerequisites are checked atomically with the
+    // changes.
+    update: Mutex<()>,
+}
+
+impl DynamicRecords {
+    // Returns the dynamic records of the name on the network.
+    pub fn get(&self, network_name: &str, name: &str) -> Option<DynamicName> {
+        let names = match self.names.read() {
+            Ok(n) => n,
+            Err(e) => e.into_inner(),
+        };
+        names.get(network_name)?.get(name).cloned()
+    }
+
+    // Returns all names with dynamic records in the zone on the network,
+    // relative to the zone.
+    pub fn names(&self, network_name: &str, zone: &str) -> Vec<String> {
+        let names = match self.names.read() {
+            Ok(n) => n,
+            Err(e) => e.into_inner(),
+        };
+        let suffix = format!(".{zone}");
+        names
+            .get(network_name)
+            .map(|n| {
+                n.keys()
+                    .filter_map(|name| name.strip_suffix(&suffix))
+                    .map(String::from)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    // Drop the records of networks which no longer exist.
+    pub fn retain_networks<V>(&self, networks: &HashMap<String, V>) {
+        let mut names = match self.names.write() {
+            Ok(n) => n,
+            Err(e) => e.into_inner(),
+        };
+        names.retain(|network, _| networks.contains_key(network));
+    }
+}
+
+// Returns the name relative to the zone, empty for the apex, or None if the
+// name is not in the zone.
+fn relative_name(zone: &str, name: &Name) -> Option<String> {
+    let name = owner_name(name);
+    if name == zone {
+        return Some(String::new());
+    }
+    name.strip_suffix(&format!(".{zone}")).map(String::from)
+}
+
+// Returns the key of the dynamic records of a name.
+fn owner_name(name: &Name) -> String {
+    name.to_string().trim_end_matches('.').to_lowercase()
+}
+
+// Returns the value of a supported record used to compare records.
+fn rdata_value(data: &RData) -> Option<String> {
+    match data {
+        RData::A(_) | RData::AAAA(_) => data.ip_addr().map(|ip| ip.to_string()),
+        RData::CNAME(target) => Some(target.0.to_string().to_lowercase()),
+        RData::TXT(txt) => Some(
+            txt.txt_data
+                .iter()
+                .map(|s| String::from_utf8_lossy(s))
+                .collect(),
+        ),
+        _ => None,
+    }
+}
+
+impl DNSBackend {
+    // Apply a DNS UPDATE to the dynamic records of the network. The zone is a
+    // search domain of the network. Returns the response code of the update,
+    // nothing is changed unless it is NoError.
+    pub fn apply_update(
+        &self,
+        requester: &IpAddr,
+        network_name: &str,
+        zone: &str,
+        prerequisites: &[Record],
+        updates: &[Record],
+    ) -> ResponseCode {
+        let _guard = match self.dynamic_records.update.lock() {
+            Ok(g) => g,
+            Err(e) => e.into_inner(),
+        };
+
+        if let Err(code) = self.check_prerequisites(requester, network_name, zone, prerequisites) {
+            return code;
+        }
+        for update in updates {
+            if let Err(code) = prescan_update(zone, update) {
+                return code;
+            }
+        }
+        for update in updates {
+            if let Some(name) = relative_name(zone, &update.name) {
+                if self.has_static_records(requester, network_name, &name) {
+                    debug!("Refusing update of {name} which has static records");
+                    return ResponseCode::Refused;
+                }
+            }
+        }
+
+        // Apply the updates to a copy so lookups done meanwhile do not block.
+        let mut names = match self.dynamic_records.names.read() {
+            Ok(n) => n.get(network_name).cloned().unwrap_or_default(),
+            Err(e) => e
+                .into_inner()
+                .get(network_name)
+                .cloned()
+                .unwrap_or_default(),
+        };
+        for update in updates {
+            Self::apply_record(&mut names, &owner_name(&update.name), update);
+        }
+        names.retain(|_, records| !records.is_empty());
+        let records: usize = names.values().map(DynamicName::len).sum();
+        if names.len() > MAX_DYNAMIC_NAMES || records > MAX_DYNAMIC_RECORDS {
+            debug!(
+                "Refusing update resulting in {} names with {records} records",
+                names.len()
+            );
+            return ResponseCode::Refused;
+        }
+
+        let mut all_names = match self.dynamic_records.names.write() {
+            Ok(n) => n,
+            Err(e) => e.into_inner(),
+        };
+        all_names.insert(network_name.to_string(), names);
+        ResponseCode::NoError
+    }
+
+    // Check the prerequisites of an update (RFC 2136 section 3.2).
+    fn check_prerequisites(
+        &self,
+        requester: &IpAddr,
+        network_name: &str,
+        zone: &str,
+        prerequisites: &[Record],
+    ) -> Result<(), ResponseCode> {
+        // Value dependent prerequisites are compared per RRset.
+        let mut rrsets: HashMap<(String, RecordType), Vec<String>> = HashMap::new();
+        for prerequisite in prerequisites {
+            relative_name(zone, &prerequisite.name).ok_or(ResponseCode::NotZone)?;
+            let name = prerequisite.name.to_string();
+            let record_type = prerequisite.record_type();
+            match prerequisite.dns_class {
+                DNSClass::ANY if record_type == RecordType::ANY => {
+                    if !self.name_exists(requester, network_name, &name) {
+                        return Err(ResponseCode::NXDomain);
+                    }
+                }
+                DNSClass::ANY => {
+                    if self
+                        .rrset_values(requester, network_name, &name, record_type)
+                        .is_empty()
+                    {
+                        return Err(ResponseCode::NXRRSet);
+                    }
+                }
+                DNSClass::NONE if record_type == RecordType::ANY => {
+                    if self.name_exists(requester, network_name, &name) {
+                        return Err(ResponseCode::YXDomain);
+                    }
+                }
+                DNSClass::NONE => {
+                    if !self
+                        .rrset_values(requester, network_name, &name, record_type)
+                        .is_empty()
+                    {
+                        return Err(ResponseCode::YXRRSet);
+                    }
+                }
+                DNSClass::IN => {
+                    let value = rdata_value(&prerequisite.data).ok_or(ResponseCode::FormErr)?;
+                    let values = rrsets
+                        .entry((name.to_lowercase(), record_type))
+                        .or_default();
+                    if !values.contains(&value) {
+                        values.push(value);
+                    }
+                }
+                _ => return Err(ResponseCode::FormErr),
+            }
+        }
+
+        for ((name, record_type), mut expected) in rrsets {
+            let mut values = self.rrset_values(requester, network_name, &name, record_type);
+            values.sort();
+            values.dedup();
+            expected.sort();
+            if values != expected {
+                return Err(ResponseCode::NXRRSet);
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the values of the records of the given type visible for the
+    // name, both from the configuration and the dynamic records.
+    fn rrset_values(
+        &self,
+        requester: &IpAddr,
+        network_name: &str,
+        name: &str,
+        record_type: RecordType,
+    ) -> Vec<String> {
+        match record_type {
+            RecordType::A | RecordType::AAAA => self
+                .lookup(requester, network_name, name)
+                .unwrap_or_default()
+                .into_iter()
+                .filter(|ip| ip.is_ipv4() == (record_type == RecordType::A))
+                .map(|ip| ip.to_string())
+                .collect(),
+            RecordType::CNAME => self
+                .lookup_cname(requester, network_name, name)
+                .map(|target| target.to_lowercase())
+                .into_iter()
+                .collect(),
+            RecordType::TXT => self
+                .lookup_txt(requester, network_name, name)
+                .unwrap_or_default(),
+            _ => Vec::new(),
+        }
+    }
+
+    // Whether the name has records in the configuration of the requester's
+    // networks, these can not be changed with updates.
+    fn has_static_records(&self, requester: &IpAddr, network_name: &str, name: &str) -> bool {
+        self.requester_networks(requester, network_name)
+            .iter()
+            .any(|net| {
+                self.name_mappings
+                    .get(net)
+                    .is_some_and(|m| m.contains_key(name))
+                    || self
+                        .group_mappings
+                        .get(net)
+                        .is_some_and(|m| m.contains_key(name))
+                    || self
+                        .cname_mappings
+                        .get(net)
+                        .is_some_and(|m| m.contains_key(name))
+                    || self
+                        .txt_mappings
+                        .get(net)
+                        .is_some_and(|m| m.contains_key(name))
+            })
+    }
+
+    // Apply a single update record (RFC 2136 section 3.4.2). Records conflicting
+    // with a CNAME are silently ignored.
+    fn apply_record(names: &mut HashMap<String, DynamicName>, name: &str, update: &Record) {
+        let record_type = update.record_type();
+        match update.dns_class {
+            DNSClass::IN => {
+                let entry = names.entry(name.to_string()).or_default();
+                let value = match rdata_value(&update.data) {
+                    Some(v) => v,
+                    None => return,
+                };
+                match &update.data {
+                    RData::CNAME(_) => {
+                        if !entry.addrs.is_empty() || !entry.txts.is_empty() {
+                            debug!("Ignoring CNAME update for {name} which has other records");
+                            return;
+                        }
+                        entry.cname = Some(value);
+                        entry.ttls.insert(record_type, update.ttl);
+                    }
+                    _ if entry.cname.is_some() => {
+                        debug!("Ignoring {record_type} update for {name} which has a CNAME");
+                    }
+                    RData::A(_) | RData::AAAA(_) => {
+                        if let Some(ip) = update.data.ip_addr() {
+                            if !entry.addrs.contains(&ip) {
+                                entry.addrs.push(ip);
+                            }
+                            entry.ttls.insert(record_type, update.ttl);
+                        }
+                    }
+                    _ => {
+                        if !entry.txts.contains(&value) {
+                            entry.txts.push(value);
+                        }
+                        entry.ttls.insert(record_type, update.ttl);
+                    }
+                }
+            }
+            DNSClass::ANY => {
+                if record_type == RecordType::ANY {
+                    names.remove(name);
+                } else if let Some(entry) = names.get_mut(name) {
+                    match record_type {
+                        RecordType::A => entry.addrs.retain(|ip| !ip.is_ipv4()),
+                        RecordType::AAAA => entry.addrs.retain(|ip| !ip.is_ipv6()),
+                        RecordType::CNAME => entry.cname = None,
+                        RecordType::TXT => entry.txts.clear(),
+                        _ => {}
+                    }
+                }
+            }
+            DNSClass::NONE => {
+                let value = rdata_value(&update.data);
+                if let (Some(entry), Some(value)) = (names.get_mut(name), value) {
+                    match &update.data {
+                        RData::A(_) | RData::AAAA(_) => {
+                            entry.addrs.retain(|ip| ip.to_string() != value)
+                        }
+                        RData::CNAME(_) if entry.cname.as_ref() == Some(&value) => {
+                            entry.cname = None
+                        }
+                        RData::TXT(_) => entry.txts.retain(|txt| *txt != value),
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Check an update record before anything is changed (RFC 2136 section
+// 3.4.1). Only A, AAAA, CNAME and TXT records below the zone apex can be
+// changed.
+fn prescan_update(zone: &str, update: &Record) -> Result<(), ResponseCode> {
+    let name = relative_name(zone, &update.name).ok_or(ResponseCode::NotZone)?;
+    let record_type = update.record_type();
+    let supported = matches!(
+        record_type,
+        RecordType::A | RecordType::AAAA | RecordType::CNAME | RecordType::TXT
+    );
+    match update.dns_class {
+        DNSClass::IN if update.data.is_update() => return Err(ResponseCode::FormErr),
+        DNSClass::IN if !supported => return Err(ResponseCode::NotImp),
+        DNSClass::IN => {}
+        DNSClass::ANY if update.ttl != 0 || !update.data.is_update() => {
+            return Err(ResponseCode::FormErr)
+        }
+        DNSClass::ANY if record_type != RecordType::ANY && !supported => {
+            return Err(ResponseCode::NotImp)
+        }
+        DNSClass::ANY => {}
+        DNSClass::NONE if update.ttl != 0 || record_type == RecordType::ANY => {
+            return Err(ResponseCode::FormErr)
+        }
+        DNSClass::NONE if !supported => return Err(ResponseCode::NotImp),
+        DNSClass::NONE => {}
+        _ => return Err(ResponseCode::FormErr),
+    }
+    if name.is_empty() {
+        debug!("Refusing update of the zone apex {zone}");
+        return Err(ResponseCode::Refused);
+    }
+    Ok(())
+}
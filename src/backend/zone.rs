@@ -0,0 +1,191 @@
+use crate::error::{AardvarkError, AardvarkResult};
+use hickory_proto::rr::{LowerName, Name, RData, Record, RecordSet, RecordType, RrKey};
+use hickory_proto::serialize::txt::Parser;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// Maximum number of CNAMEs followed within a zone before giving up, protects
+// against loops.
+const MAX_CNAME_CHAIN: usize = 8;
+
+// A zone loaded from a RFC 1035 master file which is served authoritatively to
+// the containers of a network.
+pub struct Zone {
+    pub origin: Name,
+    records: BTreeMap<RrKey, RecordSet>,
+}
+
+// Result of a lookup in a zone.
+#[derive(Debug, PartialEq)]
+pub enum ZoneAnswer {
+    // Answer records (including followed CNAMEs) and additional address
+    // records of MX, NS and SRV targets in the zone.
+    Answer(Vec<Record>, Vec<Record>),
+    // The name exists but has no records of the requested type.
+    NoData,
+    // The name does not exist in the zone.
+    NxDomain,
+}
+
+impl Zone {
+    // Parse the content of a zone file, relative names are relative to the
+    // given origin unless the file sets its own with $ORIGIN. The zone must
+    // have a SOA record at its origin.
+    pub fn parse(content: &str, path: &Path, origin: Name) -> AardvarkResult<Zone> {
+        let (origin, records) = Parser::new(content, Some(path.to_path_buf()), Some(origin))
+            .parse()
+            .map_err(|e| AardvarkError::msg(format!("invalid zone file: {e}")))?;
+        let zone = Zone { origin, records };
+        if zone.soa().is_none() {
+            return Err(AardvarkError::msg(format!(
+                "zone {} has no SOA record",
+                zone.origin
+            )));
+        }
+        Ok(zone)
+    }
+
+    // The SOA record of the zone.
+    pub fn soa(&self) -> Option<&Record> {
+        self.records
+            .get(&RrKey::new(LowerName::new(&self.origin), RecordType::SOA))?
+            .records_without_rrsigs()
+            .next()
+    }
+
+    // The SOA record to add to negative answers, its TTL is the negative
+    // caching TTL of the zone (RFC 2308).
+    pub fn negative_soa(&self) -> Option<Record> {
+        let mut soa = self.soa()?.clone();
+        if let RData::SOA(data) = &soa.data {
+            soa.ttl = soa.ttl.min(data.minimum);
+        }
+        Some(soa)
+    }
+
+    // All records of the zone except the SOA, used for zone transfers.
+    pub fn records(&self) -> impl Iterator<Item = &Record> {
+        self.records
+            .values()
+            .filter(|rrset| rrset.record_type() != RecordType::SOA)
+            .flat_map(|rrset| rrset.records_without_rrsigs())
+    }
+
+    // Whether the name is within this zone.
+    pub fn contains(&self, name: &Name) -> bool {
+        self.origin.zone_of(name)
+    }
+
+    // Look up the records of the given type, CNAMEs are followed as long as
+    // their target is in the zone.
+    pub fn lookup(&self, name: &Name, record_type: RecordType) -> ZoneAnswer {
+        let mut answers: Vec<Record> = Vec::new();
+        let mut name = name.clone();
+        for _ in 0..MAX_CNAME_CHAIN {
+            let owner = match self.find(&name) {
+                Some(owner) => owner,
+                None if answers.is_empty() => return ZoneAnswer::NxDomain,
+                None => break,
+            };
+            if let Some(records) = self.records_at(&owner, &name, record_type) {
+                answers.extend(records);
+                break;
+            }
+            if record_type == RecordType::CNAME {
+                break;
+            }
+            let cname = match self.records_at(&owner, &name, RecordType::CNAME) {
+                Some(cname) => cname,
+                None => break,
+            };
+            let target = cname.iter().find_map(|r| match &r.data {
+                RData::CNAME(target) => Some(target.0.clone()),
+                _ => None,
+            });
+            answers.extend(cname);
+            match target {
+                Some(target) if self.contains(&target) => name = target,
+                _ => break,
+            }
+        }
+
+        if answers.is_empty() {
+            return ZoneAnswer::NoData;
+        }
+        let additionals = self.additionals(&answers);
+        ZoneAnswer::Answer(answers, additionals)
+    }
+
+    // Find the owner of the records for the name: the name itself if it
+    // exists, otherwise the wildcard at its closest encloser (RFC 4592).
+    fn find(&self, name: &Name) -> Option<LowerName> {
+        let name = LowerName::new(name);
+        if self.exists(&name) {
+            return Some(name);
+        }
+        let origin = LowerName::new(&self.origin);
+        let mut encloser = name.base_name();
+        while origin.zone_of(&encloser) {
+            if self.exists(&encloser) {
+                let wildcard = LowerName::new(&Name::from(&encloser).prepend_label("*").ok()?);
+                return self.exists(&wildcard).then_some(wildcard);
+            }
+            encloser = encloser.base_name();
+        }
+        None
+    }
+
+    // A name exists if it owns records or has descendants which do (an empty
+    // non-terminal).
+    fn exists(&self, name: &LowerName) -> bool {
+        self.records.keys().any(|key| name.zone_of(&key.name))
+    }
+
+    // The records of the owner renamed to the queried name, which only differs
+    // for wildcards.
+    fn records_at(
+        &self,
+        owner: &LowerName,
+        name: &Name,
+        record_type: RecordType,
+    ) -> Option<Vec<Record>> {
+        let rrset = self.records.get(&RrKey::new(owner.clone(), record_type))?;
+        Some(
+            rrset
+                .records_without_rrsigs()
+                .map(|r| {
+                    let mut record = r.clone();
+                    record.name = name.clone();
+                    record
+                })
+                .collect(),
+        )
+    }
+
+    // Address records of the MX, NS and SRV targets in the zone.
+    fn additionals(&self, answers: &[Record]) -> Vec<Record> {
+        let mut targets: Vec<&Name> = Vec::new();
+        for record in answers {
+            let target = match &record.data {
+                RData::MX(mx) => &mx.exchange,
+                RData::NS(ns) => &ns.0,
+                RData::SRV(srv) => &srv.target,
+                _ => continue,
+            };
+            if self.contains(target) && !targets.contains(&target) {
+                targets.push(target);
+            }
+        }
+
+        let mut additionals = Vec::new();
+        for target in targets {
+            let owner = LowerName::new(target);
+            for record_type in [RecordType::A, RecordType::AAAA] {
+                if let Some(records) = self.records_at(&owner, target, record_type) {
+                    additionals.extend(records);
+                }
+            }
+        }
+        additionals
+    }
+}
@@ -1,4 +1,5 @@
 //! Runs the aardvark dns server with provided config
+use crate::config::ConfigOptions;
 use crate::error::{AardvarkError, AardvarkResult};
 use crate::server::serve;
 use clap::Parser;
@@ -14,12 +15,7 @@ impl Run {
         Self {}
     }
 
-    pub fn exec(
-        &self,
-        input_dir: String,
-        port: u16,
-        filter_search_domain: String,
-    ) -> AardvarkResult<()> {
+    pub fn exec(&self, input_dir: String, port: u16, options: ConfigOptions) -> AardvarkResult<()> {
         // create a temporary path for unix socket
         // so parent can communicate with child and
         // only exit when child is ready to serve.
@@ -53,9 +49,7 @@ impl Run {
                     )));
                 }
 
-                if let Err(er) =
-                    serve::serve(&input_dir, port, &filter_search_domain, ready_pipe_write)
-                {
+                if let Err(er) = serve::serve(&input_dir, port, &options, ready_pipe_write) {
                     return Err(AardvarkError::msg(format!("Error starting server {er}")));
                 }
                 Ok(())
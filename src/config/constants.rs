@@ -1,2 +1,6 @@
 pub static AARDVARK_PID_FILE: &str = "aardvark.pid";
 pub static INTERNAL_SUFFIX: &str = "%int";
+// Network names must not start with an underscore, so the directory never
+// collides with a network config file.
+pub static ZONES_DIR: &str = "_zones";
+pub static ZONE_FILE_EXTENSION: &str = "zone";
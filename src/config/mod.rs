@@ -1,20 +1,68 @@
-use crate::backend::DNSBackend;
+use crate::backend::zone::Zone;
+use crate::backend::{
+    normalize_search_domains, subnet_reverse_zones, AnswerOrder, ContainerEntry, DNSBackend,
+    ForwardStrategy, SrvRecord,
+};
+use crate::dns::tsig::TsigKey;
 use crate::error::{AardvarkError, AardvarkResult};
-use log::error;
+use hickory_proto::rr::Name;
+use ipnet::IpNet;
+use log::{error, warn};
 use std::collections::HashMap;
 use std::fs::{metadata, read_dir, read_to_string};
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::Path;
 use std::vec::Vec;
 pub mod constants;
 
+// Options given on the command line which change how the configuration is
+// served.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigOptions {
+    // Search domains filtered from requests, e.g. dns.podman
+    pub search_domains: Vec<String>,
+    // Answer aliases as CNAME pointing to the container name.
+    pub cname_aliases: bool,
+    // Answer reverse lookups with the names on all shared networks.
+    pub ptr_all_aliases: bool,
+    // Answer reverse lookups with the short container id as well.
+    pub ptr_short_id: bool,
+    // Order of address answers unless overridden by the network.
+    pub answer_order: AnswerOrder,
+    // TTL of local answers unless overridden by the network.
+    pub ttl: u32,
+    // Names resolving to the gateway of the network, e.g. host.containers.internal
+    pub gateway_names: Vec<String>,
+    // Subnets besides the host allowed to request zone transfers.
+    pub transfer_acl: Vec<IpNet>,
+    // Subnets besides the host allowed to send dynamic updates.
+    pub update_acl: Vec<IpNet>,
+    // Keys accepted for TSIG signed dynamic updates.
+    pub tsig_keys: Vec<TsigKey>,
+    // Maximum number of cached answers of the upstream servers, 0 disables
+    // the cache.
+    pub cache_size: usize,
+    // How queries are forwarded to several upstream servers.
+    pub forward_strategy: ForwardStrategy,
+}
+
 // Parse configuration files in the given directory.
 // Configuration files are formatted as follows:
 // The name of the file will be interpreted as the name of the network.
-// The first line must be the gateway IP(s) of the network, comma-separated.
+// The first line must be the gateway IP(s) of the network, comma-separated,
+// optionally followed by <space, comma-separated list of DNS servers> and
+// <space, comma-separated list of key=value network options>.
 // All subsequent individual lines contain info on a single container and are
 // formatted as:
 // <container ID, space, IPv4 address, space, IPv6 address, space, comma-separated list of name and aliases>
+// optionally followed by <space, comma-separated list of DNS servers> and
+// <space, comma-separated list of services> and <space, comma-separated list
+// of groups>. A service is written as service/protocol/port[/priority/weight],
+// e.g. http/tcp/80, a group is e.g. the name of the pod of the container.
+// Lines starting with "record " contain a static record not tied to a container
+// formatted as <name, space, type, space, value>.
 // Where space is a single space character.
+// Zone files of a network are read from _zones/<network>/<origin>.zone.
 // Returns a complete DNSBackend struct (all that is necessary for looks) and
 
 // Silent clippy: sometimes clippy marks useful tyes as complex and for this case following type is
@@ -22,7 +70,7 @@ pub mod constants;
 #[allow(clippy::type_complexity)]
 pub fn parse_configs(
     dir: &str,
-    filter_search_domain: &str,
+    options: &ConfigOptions,
 ) -> AardvarkResult<(
     DNSBackend,
     HashMap<String, Vec<Ipv4Addr>>,
@@ -43,6 +91,16 @@ pub fn parse_configs(
     let mut ctr_dns_server: HashMap<IpAddr, Option<Vec<IpAddr>>> = HashMap::new();
     let mut network_dns_server: HashMap<String, Vec<IpAddr>> = HashMap::new();
     let mut network_is_internal: HashMap<String, bool> = HashMap::new();
+    let mut network_services: HashMap<String, HashMap<String, Vec<SrvRecord>>> = HashMap::new();
+    let mut network_containers: HashMap<String, Vec<ContainerEntry>> = HashMap::new();
+    let mut network_listen_ips: HashMap<String, Vec<IpAddr>> = HashMap::new();
+    let mut network_reverse_zones: HashMap<String, Vec<String>> = HashMap::new();
+    let mut network_answer_order: HashMap<String, AnswerOrder> = HashMap::new();
+    let mut network_ttl: HashMap<String, u32> = HashMap::new();
+    let mut network_search_domains: HashMap<String, Vec<String>> = HashMap::new();
+    let mut network_cnames: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut network_txts: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
+    let mut network_groups: HashMap<String, HashMap<String, Vec<String>>> = HashMap::new();
 
     // Enumerate all files in the directory, read them in one by one.
     // Steadily build a map of what container has what IPs and what
@@ -63,6 +121,11 @@ pub fn parse_configs(
                         continue;
                     }
                 }
+                // zone files are loaded per network below, their directory
+                // name is not a valid network name
+                if cfg.file_type().is_ok_and(|t| t.is_dir()) {
+                    continue;
+                }
                 let parsed_network_config = match parse_config(cfg.path().as_path()) {
                     Ok(c) => c,
                     Err(e) => {
@@ -121,6 +184,48 @@ pub fn parse_configs(
                     network_dns_server.insert(network_name.clone(), Vec::new());
                 }
 
+                if let Some(order) = parsed_network_config.network_options.answer_order {
+                    network_answer_order.insert(network_name.clone(), order);
+                }
+                if let Some(ttl) = parsed_network_config.network_options.ttl {
+                    network_ttl.insert(network_name.clone(), ttl);
+                }
+                if let Some(domains) = &parsed_network_config.network_options.search_domains {
+                    network_search_domains
+                        .insert(network_name.clone(), normalize_search_domains(domains));
+                }
+
+                network_listen_ips.insert(
+                    network_name.clone(),
+                    parsed_network_config.network_bind_ip.clone(),
+                );
+                // The reverse zones cover the subnets of the network. The
+                // subnets are not known without the subnet option so the
+                // listen ips and container addresses outside of them only get
+                // a zone of their own.
+                let zones = network_reverse_zones
+                    .entry(network_name.clone())
+                    .or_default();
+                let subnets = &parsed_network_config.network_options.subnets;
+                let ctr_ips = parsed_network_config.container_entry.iter().flat_map(|e| {
+                    let v4 = e.v4.iter().flatten().map(|ip| IpAddr::V4(*ip));
+                    let v6 = e.v6.iter().flatten().map(|ip| IpAddr::V6(*ip));
+                    v4.chain(v6)
+                });
+                let addrs = parsed_network_config
+                    .network_bind_ip
+                    .iter()
+                    .copied()
+                    .chain(ctr_ips)
+                    .filter(|ip| !subnets.iter().any(|net| net.contains(ip)))
+                    .map(IpNet::from);
+                for subnet in subnets.iter().copied().chain(addrs) {
+                    for zone in subnet_reverse_zones(&subnet) {
+                        if !zones.contains(&zone) {
+                            zones.push(zone);
+                        }
+                    }
+                }
                 for ip in parsed_network_config.network_bind_ip {
                     match ip {
                         IpAddr::V4(a) => listen_ips_4
@@ -134,6 +239,30 @@ pub fn parse_configs(
                     }
                 }
 
+                // Static records are visible like container names.
+                for record in parsed_network_config.static_records {
+                    match record {
+                        StaticRecord::Addr(name, ip) => network_names
+                            .entry(network_name.clone())
+                            .or_default()
+                            .entry(name)
+                            .or_default()
+                            .push(ip),
+                        StaticRecord::Cname(name, target) => {
+                            network_cnames
+                                .entry(network_name.clone())
+                                .or_default()
+                                .insert(name, target);
+                        }
+                        StaticRecord::Txt(name, txt) => network_txts
+                            .entry(network_name.clone())
+                            .or_default()
+                            .entry(name)
+                            .or_default()
+                            .push(txt),
+                    }
+                }
+
                 for entry in parsed_network_config.container_entry {
                     // Container network membership
                     let ctr_networks = network_membership.entry(entry.id.clone()).or_default();
@@ -179,6 +308,58 @@ pub fn parse_configs(
                     let ctr_ips = container_ips.entry(entry.id.clone()).or_default();
                     ctr_ips.append(&mut new_ctr_ips.clone());
 
+                    // Keep the container metadata, a container may be listed on
+                    // several lines of the same network so merge them.
+                    let containers = network_containers.entry(network_name.clone()).or_default();
+                    let ctr_id = &entry.id;
+                    match containers.iter_mut().find(|c| &c.id == ctr_id) {
+                        Some(ctr) => {
+                            ctr.ips.extend_from_slice(&new_ctr_ips);
+                            for alias in &entry.aliases {
+                                if !ctr.aliases.contains(alias) {
+                                    ctr.aliases.push(alias.clone());
+                                }
+                            }
+                        }
+                        None => containers.push(ContainerEntry {
+                            id: entry.id.clone(),
+                            aliases: entry.aliases.clone(),
+                            ips: new_ctr_ips.clone(),
+                        }),
+                    }
+
+                    // Services are published for every name of the container and
+                    // point to the container name (first entry).
+                    if !entry.services.is_empty() {
+                        let services = network_services.entry(network_name.clone()).or_default();
+                        for service in &entry.services {
+                            let record = SrvRecord {
+                                priority: service.priority,
+                                weight: service.weight,
+                                port: service.port,
+                                target: entry.aliases[0].clone(),
+                            };
+                            for alias in &entry.aliases {
+                                services
+                                    .entry(format!(
+                                        "_{}._{}.{}",
+                                        service.service, service.protocol, alias
+                                    ))
+                                    .or_default()
+                                    .push(record.clone());
+                            }
+                        }
+                    }
+
+                    // Groups (e.g. pods) the container is a member of.
+                    let groups = network_groups.entry(network_name.clone()).or_default();
+                    for group in &entry.groups {
+                        let members = groups.entry(group.clone()).or_default();
+                        if !members.contains(&entry.id) {
+                            members.push(entry.id.clone());
+                        }
+                    }
+
                     // Network aliases to IPs map.
                     let network_aliases = network_names.entry(network_name.clone()).or_default();
                     for alias in entry.aliases {
@@ -216,19 +397,112 @@ pub fn parse_configs(
         }
     }
 
-    Ok((
-        DNSBackend::new(
-            ctrs,
-            network_names,
-            reverse,
-            ctr_dns_server,
-            network_dns_server,
-            network_is_internal,
-            filter_search_domain.to_owned(),
-        ),
-        listen_ips_4,
-        listen_ips_6,
-    ))
+    let mut backend = DNSBackend::new(
+        ctrs,
+        network_names,
+        reverse,
+        ctr_dns_server,
+        network_dns_server,
+        network_is_internal,
+        options.search_domains.clone(),
+    );
+    backend.cname_aliases = options.cname_aliases;
+    backend.ptr_all_aliases = options.ptr_all_aliases;
+    backend.ptr_short_id = options.ptr_short_id;
+    backend.answer_order = options.answer_order;
+    backend.network_answer_order = network_answer_order;
+    backend.ttl = options.ttl;
+    backend.network_ttl = network_ttl;
+    backend.network_search_domains = network_search_domains;
+    backend.transfer_acl = options.transfer_acl.clone();
+    backend.update_acl = options.update_acl.clone();
+    backend.tsig_keys = options.tsig_keys.clone();
+    backend.forward_strategy = options.forward_strategy;
+    backend.gateway_names = options
+        .gateway_names
+        .iter()
+        .map(|n| n.trim_end_matches('.').to_lowercase())
+        .filter(|n| !n.is_empty())
+        .collect();
+    backend.service_mappings = network_services;
+    backend.container_entries = network_containers;
+    backend.group_mappings = network_groups;
+    backend.cname_mappings = network_cnames;
+    backend.txt_mappings = network_txts;
+    backend.listen_ips = network_listen_ips;
+    backend.reverse_zones = network_reverse_zones;
+    backend.zones = parse_zones(dir, &backend);
+
+    Ok((backend, listen_ips_4, listen_ips_6))
+}
+
+// Load the zone files of all networks from _zones/<network>/<origin>.zone in
+// the config directory. Invalid zone files are logged and skipped like invalid
+// network configs.
+fn parse_zones(dir: &str, backend: &DNSBackend) -> HashMap<String, Vec<Zone>> {
+    let mut zones: HashMap<String, Vec<Zone>> = HashMap::new();
+    for network_name in backend.listen_ips.keys() {
+        let network_dir = Path::new(dir).join(constants::ZONES_DIR).join(network_name);
+        let files = match read_dir(&network_dir) {
+            Ok(files) => files,
+            Err(e) => {
+                if e.kind() != std::io::ErrorKind::NotFound {
+                    error!("Error listing zone files in {network_dir:?}: {e}");
+                }
+                continue;
+            }
+        };
+        for file in files.flatten() {
+            let path = file.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(constants::ZONE_FILE_EXTENSION) {
+                continue;
+            }
+            match parse_zone(&path, network_name, backend) {
+                Ok(zone) => zones.entry(network_name.clone()).or_default().push(zone),
+                Err(e) => error!("Error reading zone file {path:?}: {e}"),
+            }
+        }
+    }
+    zones
+}
+
+// Parse a single zone file, the file name without extension is the origin of
+// the zone unless the file sets one with $ORIGIN.
+fn parse_zone(path: &Path, network_name: &str, backend: &DNSBackend) -> AardvarkResult<Zone> {
+    let origin = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .and_then(|s| Name::from_ascii(format!("{}.", s.trim_end_matches('.'))).ok())
+        .ok_or_else(|| AardvarkError::msg("zone file name is not a valid domain name"))?;
+    let content = read_to_string(path)?;
+    let zone = Zone::parse(&content, path, origin)?;
+
+    // The search domains and reverse zones are served from the container
+    // entries, a zone file must not shadow them.
+    let origin = zone.origin.to_string();
+    let origin = origin.trim_end_matches('.');
+    if let Some(conflict) = backend.authoritative_zone(network_name, origin) {
+        return Err(AardvarkError::msg(format!(
+            "zone {origin} overlaps with zone {conflict} served by aardvark-dns"
+        )));
+    }
+    let covered = backend
+        .search_domains(network_name)
+        .iter()
+        .chain(
+            backend
+                .reverse_zones
+                .get(network_name)
+                .into_iter()
+                .flatten(),
+        )
+        .find(|domain| domain.ends_with(&format!(".{origin}")));
+    if let Some(domain) = covered {
+        return Err(AardvarkError::msg(format!(
+            "zone {origin} overlaps with zone {domain} served by aardvark-dns"
+        )));
+    }
+    Ok(zone)
 }
 
 // A single entry in a config file
@@ -238,6 +512,56 @@ struct CtrEntry {
     v6: Option<Vec<Ipv6Addr>>,
     aliases: Vec<String>,
     dns_servers: Option<Vec<IpAddr>>,
+    services: Vec<CtrService>,
+    groups: Vec<String>,
+}
+
+// A service published by a container, served as SRV record
+struct CtrService {
+    service: String,
+    protocol: String,
+    port: u16,
+    priority: u16,
+    weight: u16,
+}
+
+// Parse the comma separated key=value options of a network. Unknown and
+// invalid options are logged and ignored so the network is still served.
+fn parse_network_options(path: &Path, options: &str) -> NetworkOptions {
+    let mut network_options = NetworkOptions::default();
+    for option in options.split(',') {
+        let result = match option.split_once('=') {
+            Some(("order", value)) => value.parse().map(|order| {
+                network_options.answer_order = Some(order);
+            }),
+            // May be given several times, an empty value disables the
+            // search domains on the network.
+            Some(("search_domain", value)) => {
+                network_options
+                    .search_domains
+                    .get_or_insert_with(Vec::new)
+                    .push(value.to_string());
+                Ok(())
+            }
+            Some(("ttl", value)) => value
+                .parse()
+                .map(|ttl| network_options.ttl = Some(ttl))
+                .map_err(|e| format!("invalid ttl {value}: {e}")),
+            // May be given several times, once for every subnet.
+            Some(("subnet", value)) => value
+                .parse()
+                .map(|subnet| network_options.subnets.push(subnet))
+                .map_err(|e| format!("invalid subnet {value}: {e}")),
+            _ => Err("unknown option".to_string()),
+        };
+        if let Err(e) = result {
+            warn!(
+                "Ignoring network option {option} in {}: {e}",
+                path.display()
+            );
+        }
+    }
+    network_options
 }
 
 // A simplified type for results retured by
@@ -247,6 +571,40 @@ struct ParsedNetworkConfig {
     network_bind_ip: Vec<IpAddr>,
     container_entry: Vec<CtrEntry>,
     network_dnsservers: Vec<IpAddr>,
+    network_options: NetworkOptions,
+    static_records: Vec<StaticRecord>,
+}
+
+// Prefix of a static record line in a network config.
+const STATIC_RECORD_PREFIX: &str = "record ";
+
+// A static record of a network which is not tied to a container.
+enum StaticRecord {
+    // An A or AAAA record.
+    Addr(String, IpAddr),
+    // A CNAME record, the target is fully qualified if it ends with a dot and
+    // relative to the search domain otherwise.
+    Cname(String, String),
+    Txt(String, String),
+}
+
+impl StaticRecord {
+    fn name(&self) -> &String {
+        match self {
+            StaticRecord::Addr(name, _) => name,
+            StaticRecord::Cname(name, _) => name,
+            StaticRecord::Txt(name, _) => name,
+        }
+    }
+}
+
+// Options of a network given in the third column of the first line.
+#[derive(Default)]
+struct NetworkOptions {
+    answer_order: Option<AnswerOrder>,
+    ttl: Option<u32>,
+    search_domains: Option<Vec<String>>,
+    subnets: Vec<IpNet>,
 }
 
 // Read and parse a single given configuration file
@@ -256,7 +614,9 @@ fn parse_config(path: &std::path::Path) -> AardvarkResult<ParsedNetworkConfig> {
 
     let mut bind_addrs: Vec<IpAddr> = Vec::new();
     let mut network_dns_servers: Vec<IpAddr> = Vec::new();
+    let mut network_options = NetworkOptions::default();
     let mut ctrs: Vec<CtrEntry> = Vec::new();
+    let mut records: Vec<StaticRecord> = Vec::new();
 
     // Split on newline, parse each line
     for line in content.split('\n') {
@@ -287,7 +647,7 @@ fn parse_config(path: &std::path::Path) -> AardvarkResult<ParsedNetworkConfig> {
             // If network parts contain more than one col then
             // we have custom dns server also defined at network level
             // lets process that.
-            if network_parts.len() > 1 {
+            if network_parts.len() > 1 && !network_parts[1].is_empty() {
                 for ip in network_parts[1].split(',') {
                     let local_ip = match ip.parse() {
                         Ok(l) => l,
@@ -301,10 +661,32 @@ fn parse_config(path: &std::path::Path) -> AardvarkResult<ParsedNetworkConfig> {
                 }
             }
 
+            // An optional third column holds comma separated key=value
+            // options of the network.
+            if network_parts.len() > 2 && !network_parts[2].is_empty() {
+                network_options = parse_network_options(path, network_parts[2]);
+            }
+
             is_first = false;
             continue;
         }
 
+        // Static records are not tied to a container.
+        if let Some(record) = line.strip_prefix(STATIC_RECORD_PREFIX) {
+            match parse_static_record(record) {
+                Ok(r) => records.push(r),
+                Err(e) => {
+                    return Err(AardvarkError::msg(format!(
+                        "configuration file {} line {} has an invalid record: {}",
+                        path.to_string_lossy(),
+                        line,
+                        e
+                    )))
+                }
+            }
+            continue;
+        }
+
         // Split on space
         let parts = line.split(' ').collect::<Vec<&str>>();
         if parts.len() < 4 {
@@ -358,7 +740,20 @@ fn parse_config(path: &std::path::Path) -> AardvarkResult<ParsedNetworkConfig> {
             )));
         }
 
-        let dns_servers: Option<Vec<IpAddr>> = if parts.len() == 5 && !parts[4].is_empty() {
+        // Wildcards are only supported as the first label of a name, e.g. *.foo
+        if let Some(alias) = aliases
+            .iter()
+            .find(|a| a.contains('*') && (!a.starts_with("*.") || a[2..].contains('*')))
+        {
+            return Err(AardvarkError::msg(format!(
+                "configuration file {} line {} has invalid wildcard name {}",
+                path.to_string_lossy(),
+                line,
+                alias
+            )));
+        }
+
+        let dns_servers: Option<Vec<IpAddr>> = if parts.len() >= 5 && !parts[4].is_empty() {
             let dns_server = match parts[4].split(',').map(|i| i.parse()).collect() {
                 Ok(i) => i,
                 Err(e) => {
@@ -373,12 +768,34 @@ fn parse_config(path: &std::path::Path) -> AardvarkResult<ParsedNetworkConfig> {
             None
         };
 
+        let services: Vec<CtrService> = if parts.len() >= 6 && !parts[5].is_empty() {
+            match parts[5].split(',').map(parse_service).collect() {
+                Ok(s) => s,
+                Err(e) => {
+                    return Err(AardvarkError::msg(format!(
+                        "error parsing services {}: {}",
+                        parts[5], e
+                    )))
+                }
+            }
+        } else {
+            Vec::new()
+        };
+
+        let groups: Vec<String> = if parts.len() >= 7 && !parts[6].is_empty() {
+            parts[6].split(',').map(|g| g.to_lowercase()).collect()
+        } else {
+            Vec::new()
+        };
+
         ctrs.push(CtrEntry {
             id: parts[0].to_string().to_lowercase(),
             v4: v4_addrs,
             v6: v6_addrs,
             aliases,
             dns_servers,
+            services,
+            groups,
         });
     }
 
@@ -390,9 +807,95 @@ fn parse_config(path: &std::path::Path) -> AardvarkResult<ParsedNetworkConfig> {
         )));
     }
 
+    // A name with a CNAME must not have any other records.
+    for record in &records {
+        if let StaticRecord::Cname(name, _) = record {
+            let conflicts = records.iter().filter(|r| r.name() == name).count() > 1
+                || ctrs.iter().any(|c| c.aliases.contains(name));
+            if conflicts {
+                return Err(AardvarkError::msg(format!(
+                    "configuration file {} has other records for CNAME {}",
+                    path.to_string_lossy(),
+                    name
+                )));
+            }
+        }
+    }
+
     Ok(ParsedNetworkConfig {
         network_bind_ip: bind_addrs,
         container_entry: ctrs,
         network_dnsservers: network_dns_servers,
+        network_options,
+        static_records: records,
+    })
+}
+
+// Parse a static record in the format <name> <type> <value>, the value of a
+// TXT record is the rest of the line.
+fn parse_static_record(record: &str) -> AardvarkResult<StaticRecord> {
+    let fields = record.splitn(3, ' ').collect::<Vec<&str>>();
+    if fields.len() != 3 || fields[0].is_empty() || fields[2].is_empty() {
+        return Err(AardvarkError::msg(
+            "record must be formatted as <name> <type> <value>",
+        ));
+    }
+
+    let name = fields[0].trim_end_matches('.').to_lowercase();
+    let value = fields[2];
+    match fields[1].to_uppercase().as_str() {
+        "A" => match value.parse::<Ipv4Addr>() {
+            Ok(ip) => Ok(StaticRecord::Addr(name, IpAddr::V4(ip))),
+            Err(e) => Err(AardvarkError::msg(format!(
+                "invalid ipv4 address {value}: {e}"
+            ))),
+        },
+        "AAAA" => match value.parse::<Ipv6Addr>() {
+            Ok(ip) => Ok(StaticRecord::Addr(name, IpAddr::V6(ip))),
+            Err(e) => Err(AardvarkError::msg(format!(
+                "invalid ipv6 address {value}: {e}"
+            ))),
+        },
+        "CNAME" => Ok(StaticRecord::Cname(name, value.to_lowercase())),
+        "TXT" => Ok(StaticRecord::Txt(name, value.to_string())),
+        t => Err(AardvarkError::msg(format!("unsupported record type {t}"))),
+    }
+}
+
+// Parse a single service entry in the format service/protocol/port[/priority/weight]
+fn parse_service(service: &str) -> AardvarkResult<CtrService> {
+    let fields = service.split('/').collect::<Vec<&str>>();
+    if fields.len() != 3 && fields.len() != 5 {
+        return Err(AardvarkError::msg(format!(
+            "service {service} must be formatted as service/protocol/port[/priority/weight]"
+        )));
+    }
+    if fields[0].is_empty() || fields[1].is_empty() {
+        return Err(AardvarkError::msg(format!(
+            "service {service} has an empty service or protocol name"
+        )));
+    }
+
+    let parse_num = |value: &str, what: &str| -> AardvarkResult<u16> {
+        value
+            .parse()
+            .map_err(|e| AardvarkError::msg(format!("invalid {what} {value}: {e}")))
+    };
+
+    let (priority, weight) = if fields.len() == 5 {
+        (
+            parse_num(fields[3], "priority")?,
+            parse_num(fields[4], "weight")?,
+        )
+    } else {
+        (0, 0)
+    };
+
+    Ok(CtrService {
+        service: fields[0].to_lowercase(),
+        protocol: fields[1].to_lowercase(),
+        port: parse_num(fields[2], "port")?,
+        priority,
+        weight,
     })
 }
@@ -0,0 +1,369 @@
+use hickory_proto::op::{Message, ResponseCode};
+use hickory_proto::rr::{DNSClass, RData, RecordType};
+use std::collections::{BTreeMap, HashMap};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// Upper bound of the TTL of cached answers.
+const MAX_TTL: u32 = 86400;
+// Upper bound of the TTL of cached negative answers, as recommended by
+// RFC 2308 section 5.
+const MAX_NEGATIVE_TTL: u32 = 10800;
+
+// Everything the upstream answer depends on. Containers may use different
+// upstream servers so they are part of the key.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CacheKey {
+    name: String,
+    record_type: RecordType,
+    dns_class: DNSClass,
+    recursion_desired: bool,
+    checking_disabled: bool,
+    dnssec_ok: bool,
+    nameservers: Vec<SocketAddr>,
+}
+
+struct CacheEntry {
+    msg: Message,
+    inserted: Instant,
+    expires: Instant,
+    // Position of the entry in the expiry index.
+    seq: u64,
+}
+
+// The cached answers with an index of their expiry so the answers to evict are
+// found without looking at all of them.
+#[derive(Default)]
+struct CacheEntries {
+    answers: HashMap<CacheKey, CacheEntry>,
+    // Keys ordered by expiry, the sequence number keeps answers expiring at
+    // the same time apart.
+    expiry: BTreeMap<(Instant, u64), CacheKey>,
+    next_seq: u64,
+}
+
+impl CacheEntries {
+    fn len(&self) -> usize {
+        self.answers.len()
+    }
+
+    fn insert(&mut self, key: CacheKey, msg: Message, inserted: Instant, expires: Instant) {
+        self.remove(&key);
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.expiry.insert((expires, seq), key.clone());
+        self.answers.insert(
+            key,
+            CacheEntry {
+                msg,
+                inserted,
+                expires,
+                seq,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(entry) = self.answers.remove(key) {
+            self.expiry.remove(&(entry.expires, entry.seq));
+        }
+    }
+
+    // Evict the answer closest to expiry, expired answers come first.
+    fn evict_first(&mut self) {
+        if let Some((_, key)) = self.expiry.pop_first() {
+            self.answers.remove(&key);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.answers.clear();
+        self.expiry.clear();
+    }
+}
+
+// Cache of the answers of upstream servers shared by all listeners. It holds
+// at most `capacity` answers, the ones closest to expiry (or already expired)
+// are evicted first.
+pub struct ResponseCache {
+    capacity: usize,
+    entries: Mutex<CacheEntries>,
+}
+
+impl CacheKey {
+    fn new(req: &Message, nameservers: &[SocketAddr]) -> Option<CacheKey> {
+        let query = req.queries.first()?;
+        // The order of the servers changes with options rotate.
+        let mut nameservers = nameservers.to_vec();
+        nameservers.sort();
+        Some(CacheKey {
+            name: query.name().to_lowercase().to_string(),
+            record_type: query.query_type(),
+            dns_class: query.query_class(),
+            recursion_desired: req.recursion_desired,
+            checking_disabled: req.checking_disabled,
+            dnssec_ok: req.edns.as_ref().is_some_and(|e| e.flags().dnssec_ok),
+            nameservers,
+        })
+    }
+}
+
+impl ResponseCache {
+    // A capacity of 0 disables the cache.
+    pub fn new(capacity: usize) -> ResponseCache {
+        ResponseCache {
+            capacity,
+            entries: Mutex::new(CacheEntries::default()),
+        }
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, CacheEntries> {
+        match self.entries.lock() {
+            Ok(e) => e,
+            Err(e) => e.into_inner(),
+        }
+    }
+
+    // Returns the cached answer to the request from the given upstream
+    // servers with the TTLs reduced by the time spent in the cache.
+    pub fn get(&self, req: &Message, nameservers: &[SocketAddr]) -> Option<Message> {
+        self.get_at(req, nameservers, Instant::now())
+    }
+
+    fn get_at(&self, req: &Message, nameservers: &[SocketAddr], now: Instant) -> Option<Message> {
+        if self.capacity == 0 {
+            return None;
+        }
+        let key = CacheKey::new(req, nameservers)?;
+        let mut entries = self.entries();
+        let entry = entries.answers.get(&key)?;
+        if entry.expires <= now {
+            entries.remove(&key);
+            return None;
+        }
+
+        let elapsed = now.duration_since(entry.inserted).as_secs() as u32;
+        let mut msg = entry.msg.clone();
+        msg.metadata.id = req.id;
+        msg.queries.clone_from(&req.queries);
+        for record in msg
+            .answers
+            .iter_mut()
+            .chain(msg.authorities.iter_mut())
+            .chain(msg.additionals.iter_mut())
+        {
+            record.ttl = record.ttl.saturating_sub(elapsed);
+        }
+        Some(msg)
+    }
+
+    // Cache the answer of the upstream servers to the request if it may be
+    // cached.
+    pub fn insert(&self, req: &Message, nameservers: &[SocketAddr], msg: &Message) {
+        self.insert_at(req, nameservers, msg, Instant::now())
+    }
+
+    fn insert_at(&self, req: &Message, nameservers: &[SocketAddr], msg: &Message, now: Instant) {
+        if self.capacity == 0 {
+            return;
+        }
+        let (key, ttl) = match (CacheKey::new(req, nameservers), cache_ttl(msg)) {
+            (Some(key), Some(ttl)) if ttl > 0 => (key, ttl),
+            _ => return,
+        };
+
+        let mut entries = self.entries();
+        if entries.len() >= self.capacity && !entries.answers.contains_key(&key) {
+            entries.evict_first();
+        }
+        entries.insert(key, msg.clone(), now, now + Duration::from_secs(ttl as u64));
+    }
+
+    // Drop all cached answers, e.g. when the upstream servers change.
+    pub fn flush(&self) {
+        self.entries().clear();
+    }
+}
+
+// How long the answer may be cached: the lowest TTL of its records for
+// positive answers. Negative answers (NXDOMAIN or no data) are cached for the
+// TTL of the SOA in the authority section capped by its minimum field
+// (RFC 2308), without SOA they are not cached. Truncated answers and errors
+// are never cached.
+fn cache_ttl(msg: &Message) -> Option<u32> {
+    if msg.truncation {
+        return None;
+    }
+    let negative = match msg.response_code {
+        ResponseCode::NoError => msg.answers.is_empty(),
+        ResponseCode::NXDomain => true,
+        _ => return None,
+    };
+
+    if negative {
+        let ttl = msg.authorities.iter().find_map(|r| match &r.data {
+            RData::SOA(soa) => Some(r.ttl.min(soa.minimum)),
+            _ => None,
+        })?;
+        return Some(ttl.min(MAX_NEGATIVE_TTL));
+    }
+    let ttl = msg
+        .answers
+        .iter()
+        .chain(msg.authorities.iter())
+        .chain(msg.additionals.iter())
+        .map(|r| r.ttl)
+        .min()?;
+    Some(ttl.min(MAX_TTL))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_proto::op::{MessageType, OpCode, Query};
+    use hickory_proto::rr::rdata::{A, SOA};
+    use hickory_proto::rr::{Name, Record};
+
+    fn name(n: &str) -> Name {
+        Name::from_ascii(n).unwrap()
+    }
+
+    fn request(id: u16, qname: &str) -> Message {
+        let mut msg = Message::new(id, MessageType::Query, OpCode::Query);
+        msg.add_query(Query::query(name(qname), RecordType::A));
+        msg.metadata.recursion_desired = true;
+        msg
+    }
+
+    fn answer(req: &Message, ttl: u32) -> Message {
+        let mut msg = req.clone().into_response();
+        msg.add_answer(Record::from_rdata(
+            name("example.com."),
+            ttl,
+            RData::A(A::new(192, 0, 2, 1)),
+        ));
+        msg
+    }
+
+    fn negative(req: &Message, code: ResponseCode, ttl: u32, minimum: u32) -> Message {
+        let mut msg = req.clone().into_response();
+        msg.metadata.response_code = code;
+        msg.add_authority(Record::from_rdata(
+            name("com."),
+            ttl,
+            RData::SOA(SOA::new(
+                name("ns.com."),
+                name("hostmaster.com."),
+                1,
+                3600,
+                600,
+                86400,
+                minimum,
+            )),
+        ));
+        msg
+    }
+
+    #[test]
+    fn test_cache_ttl() {
+        let nameservers: Vec<SocketAddr> = vec!["192.0.2.53:53".parse().unwrap()];
+        let cache = ResponseCache::new(10);
+        let now = Instant::now();
+        let req = request(1, "example.com.");
+        cache.insert_at(&req, &nameservers, &answer(&req, 60), now);
+
+        // Hits are case insensitive and answered with the id and question of
+        // the request and the remaining TTL.
+        let hit = cache
+            .get_at(
+                &request(2, "Example.COM."),
+                &nameservers,
+                now + Duration::from_secs(20),
+            )
+            .unwrap();
+        assert_eq!(hit.id, 2);
+        assert_eq!(hit.queries[0].name(), &name("Example.COM."));
+        assert_eq!(hit.answers[0].ttl, 40);
+
+        // Other upstream servers have their own entries.
+        let other: Vec<SocketAddr> = vec!["198.51.100.53:53".parse().unwrap()];
+        assert!(cache.get_at(&req, &other, now).is_none());
+        assert!(cache
+            .get_at(&req, &nameservers, now + Duration::from_secs(60))
+            .is_none());
+
+        // TTL 0, errors and truncated answers are not cached.
+        cache.insert_at(&req, &nameservers, &answer(&req, 0), now);
+        assert!(cache.get_at(&req, &nameservers, now).is_none());
+        let mut servfail = answer(&req, 60);
+        servfail.metadata.response_code = ResponseCode::ServFail;
+        assert_eq!(cache_ttl(&servfail), None);
+        let mut truncated = answer(&req, 60);
+        truncated.metadata.truncation = true;
+        assert_eq!(cache_ttl(&truncated), None);
+
+        cache.insert_at(&req, &nameservers, &answer(&req, 60), now);
+        cache.flush();
+        assert!(cache.get_at(&req, &nameservers, now).is_none());
+    }
+
+    #[test]
+    fn test_cache_negative() {
+        let req = request(1, "missing.example.com.");
+        assert_eq!(
+            cache_ttl(&negative(&req, ResponseCode::NXDomain, 3600, 300)),
+            Some(300)
+        );
+        assert_eq!(
+            cache_ttl(&negative(&req, ResponseCode::NoError, 60, 300)),
+            Some(60)
+        );
+        assert_eq!(
+            cache_ttl(&negative(&req, ResponseCode::NXDomain, 86400, 86400)),
+            Some(MAX_NEGATIVE_TTL)
+        );
+        // Negative answers without SOA are not cached.
+        let mut nxdomain = req.clone().into_response();
+        nxdomain.metadata.response_code = ResponseCode::NXDomain;
+        assert_eq!(cache_ttl(&nxdomain), None);
+    }
+
+    #[test]
+    fn test_cache_capacity() {
+        let nameservers: Vec<SocketAddr> = vec!["192.0.2.53:53".parse().unwrap()];
+        let cache = ResponseCache::new(2);
+        let now = Instant::now();
+        let a = request(1, "a.example.com.");
+        let b = request(2, "b.example.com.");
+        let c = request(3, "c.example.com.");
+        cache.insert_at(&a, &nameservers, &answer(&a, 30), now);
+        cache.insert_at(&b, &nameservers, &answer(&b, 60), now);
+        cache.insert_at(&c, &nameservers, &answer(&c, 60), now);
+
+        // The answer closest to expiry is evicted.
+        assert!(cache.get_at(&a, &nameservers, now).is_none());
+        assert!(cache.get_at(&b, &nameservers, now).is_some());
+        assert!(cache.get_at(&c, &nameservers, now).is_some());
+
+        // Answers replaced with a longer TTL move in the expiry order, expired
+        // answers are evicted first.
+        let later = now + Duration::from_secs(45);
+        cache.insert_at(&b, &nameservers, &answer(&b, 120), now);
+        cache.insert_at(&a, &nameservers, &answer(&a, 30), later);
+        assert!(cache.get_at(&b, &nameservers, later).is_some());
+        assert!(cache.get_at(&c, &nameservers, later).is_none());
+        let expired = now + Duration::from_secs(100);
+        cache.insert_at(&c, &nameservers, &answer(&c, 60), expired);
+        assert!(cache.get_at(&b, &nameservers, expired).is_some());
+        assert!(cache.get_at(&c, &nameservers, expired).is_some());
+        let entries = cache.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries.expiry.len(), 2);
+        drop(entries);
+
+        let disabled = ResponseCache::new(0);
+        disabled.insert_at(&a, &nameservers, &answer(&a, 30), now);
+        assert!(disabled.get_at(&a, &nameservers, now).is_none());
+    }
+}
@@ -1,7 +1,12 @@
-use crate::backend::DNSBackend;
+use crate::backend::zone::{Zone, ZoneAnswer};
+use crate::backend::{reverse_name_to_ip, DNSBackend, ForwardStrategy, NAMESERVER_NAME};
+use crate::dns::cache::ResponseCache;
+use crate::dns::tsig::{self, TsigRequest};
+use crate::dns::upstream::{UpstreamPolicy, Upstreams};
 use crate::error::AardvarkResult;
 use arc_swap::ArcSwap;
 use arc_swap::Guard;
+use futures_util::stream::FuturesUnordered;
 use futures_util::StreamExt;
 use futures_util::TryStreamExt;
 use hickory_net::{
@@ -15,19 +20,41 @@ use hickory_net::{
     DnsStreamHandle,
 };
 use hickory_proto::{
-    op::{DnsRequest, Message, ResponseCode, SerialMessage},
+    op::{DnsRequest, Edns, Message, MessageType, OpCode, Query, ResponseCode, SerialMessage},
     rr::{RData, Record, RecordType},
+    serialize::binary::BinEncodable,
 };
 use log::{debug, error, trace, warn};
 use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
 use std::sync::Mutex;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::net::TcpListener;
 use tokio::net::UdpSocket;
 
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
 
+// Time after which the next upstream server is queried with the staggered
+// forward strategy.
+const STAGGER_DELAY: Duration = Duration::from_millis(300);
+
+// Interval and timeout of the queries checking if dead upstream servers
+// recovered.
+const PROBE_INTERVAL: Duration = Duration::from_secs(30);
+const PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+// UDP payload size we advertise and answer with at most, as recommended by
+// the DNS flag day 2020.
+const EDNS_MAX_PAYLOAD: u16 = 1232;
+
+// SOA timers of the search domain zone, only relevant for secondary servers.
+const SOA_REFRESH: i32 = 3600;
+const SOA_RETRY: i32 = 600;
+const SOA_EXPIRE: i32 = 86400;
+
+// Maximum size of the records in a single zone transfer message.
+const AXFR_MESSAGE_SIZE: usize = 16384;
+
 pub const DNS_PORT: u16 = 53;
 
 pub struct CoreDns {
@@ -37,17 +64,32 @@ pub struct CoreDns {
 
 #[derive(Clone)]
 struct CoreDnsData {
-    network_name: String,                     // raw network name
-    backend: &'static ArcSwap<DNSBackend>,    // server's data store
-    no_proxy: bool,                           // do not forward to external resolvers
-    nameservers: Arc<Mutex<Vec<SocketAddr>>>, // host nameservers from resolv.conf
+    network_name: String,                  // raw network name
+    backend: &'static ArcSwap<DNSBackend>, // server's data store
+    no_proxy: bool,                        // do not forward to external resolvers
+    upstreams: Arc<Mutex<Upstreams>>,      // host nameservers from resolv.conf
+    cache: Arc<ResponseCache>,             // answers of the upstream servers
 }
 
+#[derive(Clone, Copy)]
 enum Protocol {
     Udp,
     Tcp,
 }
 
+// Result of parsing and validating an incoming message.
+enum ParsedMessage {
+    // A standard query with a single question.
+    Query(Name, RecordType, Message),
+    // A dynamic update of the given zone with the raw message needed to verify
+    // its signature.
+    Update(Name, Message, Vec<u8>),
+    // An invalid request which must be answered with the given error.
+    Error(Message),
+    // Garbage or responses which are dropped.
+    Ignore,
+}
+
 impl CoreDns {
     // Most of the arg can be removed in design refactor.
     // so dont create a struct for this now.
@@ -56,7 +98,8 @@ impl CoreDns {
         backend: &'static ArcSwap<DNSBackend>,
         rx: flume::Receiver<()>,
         no_proxy: bool,
-        nameservers: Arc<Mutex<Vec<SocketAddr>>>,
+        upstreams: Arc<Mutex<Upstreams>>,
+        cache: Arc<ResponseCache>,
     ) -> Self {
         CoreDns {
             rx,
@@ -64,7 +107,8 @@ impl CoreDns {
                 network_name,
                 backend,
                 no_proxy,
-                nameservers,
+                upstreams,
+                cache,
             },
         }
     }
@@ -161,10 +205,33 @@ impl CoreDns {
         let backend = data.backend.load();
         let src_address = msg.addr();
         let mut sender = sender_original.with_remote_addr(src_address);
-        let (request_name, record_type, mut req) = match parse_dns_msg(msg) {
-            Some((name, record_type, req)) => (name, record_type, req),
-            _ => {
-                error!("None received while parsing dns message, this is not expected server will ignore this message");
+        let (request_name, record_type, req) = match parse_dns_msg(msg) {
+            ParsedMessage::Query(name, record_type, req) => (name, record_type, req),
+            ParsedMessage::Update(zone, req, bytes) => {
+                let (mut msg, signed) = reply_update(
+                    &zone,
+                    &data.network_name,
+                    &backend,
+                    src_address,
+                    &req,
+                    &bytes,
+                );
+                msg = response(&msg, req.edns.as_ref());
+                if let Some(signed) = signed {
+                    if let Err(e) = tsig::sign_response(&signed, &mut msg) {
+                        error!("{e}");
+                        return;
+                    }
+                }
+                send(&mut sender, src_address, &msg, req.edns.as_ref(), proto);
+                return;
+            }
+            ParsedMessage::Error(msg) => {
+                reply(&mut sender, src_address, &msg, msg.edns.as_ref(), proto);
+                return;
+            }
+            ParsedMessage::Ignore => {
+                debug!("Ignoring invalid dns message from {src_address}");
                 return;
             }
         };
@@ -181,45 +248,145 @@ impl CoreDns {
         trace!("server backend.name_mappings: {:?}", backend.name_mappings);
         trace!("server backend.ip_mappings: {:?}", backend.ip_mappings);
 
-        match record_type {
-            RecordType::PTR => {
-                if let Some(msg) = reply_ptr(&request_name_string, &backend, src_address, &req) {
-                    reply(&mut sender, src_address, &msg);
-                    return;
-                }
-                // No match found, forwarding below.
+        let network_name = data.network_name.as_str();
+        if record_type == RecordType::AXFR {
+            for msg in reply_axfr(
+                &request_name_string,
+                &request_name,
+                network_name,
+                &backend,
+                src_address,
+                &req,
+                proto,
+            ) {
+                reply(&mut sender, src_address, &msg, req.edns.as_ref(), proto);
             }
-            RecordType::A | RecordType::AAAA => {
-                if let Some(msg) = reply_ip(
+            return;
+        }
+
+        // Names in the zone files of the network are only answered from the
+        // zone, they are never forwarded.
+        if let Some(zone) = backend.lookup_zone(network_name, &request_name) {
+            let msg = reply_zone_file(zone, &request_name, record_type, &req);
+            reply(&mut sender, src_address, &msg, req.edns.as_ref(), proto);
+            return;
+        }
+
+        let local_reply = match record_type {
+            t if answers_with_cname(
+                t,
+                &request_name_string,
+                network_name,
+                &backend,
+                src_address,
+            ) =>
+            {
+                reply_cname(
                     &request_name_string,
                     &request_name,
-                    &data.network_name,
-                    record_type,
+                    network_name,
                     &backend,
                     src_address,
-                    &mut req,
-                ) {
-                    reply(&mut sender, src_address, msg);
-                    return;
-                }
-                // No match found, forwarding below.
+                    &req,
+                )
             }
-
-            // TODO: handle MX here like docker does
+            RecordType::PTR => reply_ptr(
+                &request_name_string,
+                network_name,
+                &backend,
+                src_address,
+                &req,
+            ),
+            RecordType::A | RecordType::AAAA => reply_ip(
+                &request_name_string,
+                &request_name,
+                network_name,
+                record_type,
+                &backend,
+                src_address,
+                &req,
+            ),
+            RecordType::CNAME => reply_cname(
+                &request_name_string,
+                &request_name,
+                network_name,
+                &backend,
+                src_address,
+                &req,
+            ),
+            RecordType::SRV => reply_srv(
+                &request_name_string,
+                &request_name,
+                network_name,
+                &backend,
+                src_address,
+                &req,
+            ),
+            RecordType::TXT => reply_txt(
+                &request_name_string,
+                &request_name,
+                network_name,
+                &backend,
+                src_address,
+                &req,
+            ),
+            RecordType::MX => reply_mx(
+                &request_name_string,
+                &request_name,
+                network_name,
+                &backend,
+                src_address,
+                &req,
+            ),
+            RecordType::SOA | RecordType::NS => reply_zone(
+                &request_name_string,
+                &request_name,
+                network_name,
+                record_type,
+                &backend,
+                &req,
+            ),
 
             // We do not handle this request type so do nothing,
             // we forward the request to upstream resolvers below.
-            _ => {}
+            _ => None,
         };
 
+        if let Some(mut msg) = local_reply {
+            // We are the authority for all names we answer locally.
+            msg.metadata.authoritative = true;
+            if msg.answers.is_empty() {
+                if let Some(zone) = backend.authoritative_zone(network_name, &request_name_string) {
+                    add_soa_authority(&mut msg, &backend, network_name, &zone);
+                }
+            }
+            reply(&mut sender, src_address, &msg, req.edns.as_ref(), proto);
+            return;
+        }
+        // No match found, forwarding below.
+
         // are we allowed to forward?
-        if data.no_proxy
-            || backend.ctr_is_internal(&src_address.ip())
-            || request_name_string.ends_with(&backend.search_domain)
-        {
+        let zone = backend.authoritative_zone(network_name, &request_name_string);
+        if data.no_proxy || backend.ctr_is_internal(&src_address.ip()) || zone.is_some() {
             let mut nx_message = req.into_response();
-            nx_message.metadata.response_code = ResponseCode::NXDomain;
-            reply(&mut sender, src_address, &nx_message);
+            if let Some(zone) = zone {
+                // Names in our zones are answered authoritatively, with
+                // NODATA if the name exists with other types.
+                nx_message.metadata.authoritative = true;
+                if !backend.name_exists(&src_address.ip(), network_name, &request_name_string) {
+                    nx_message.metadata.response_code = ResponseCode::NXDomain;
+                }
+                add_soa_authority(&mut nx_message, &backend, network_name, &zone);
+            } else {
+                nx_message.metadata.response_code = ResponseCode::NXDomain;
+            }
+            reply(
+                &mut sender,
+                src_address,
+                &nx_message,
+                nx_message.edns.as_ref(),
+                proto,
+            );
         } else {
             debug!(
                 "Forwarding dns request for {} type: {}",
@@ -241,13 +408,25 @@ impl CoreDns {
             }
             // Use host resolvers if no custom resolvers are set for the container.
             if nameservers.is_empty() {
-                nameservers.clone_from(&data.nameservers.lock().expect("lock nameservers"));
+                nameservers.clone_from(&data.upstreams.lock().expect("lock upstreams").nameservers);
+            }
+
+            if let Some(msg) = data.cache.get(&req, &nameservers) {
+                debug!("[{}] answering {request_name_string} from cache", req.id);
+                reply(&mut sender, src_address, &msg, req.edns.as_ref(), proto);
+                return;
             }
 
+            let upstreams = data.upstreams.clone();
+            let strategy = backend.forward_strategy;
+            let cache = data.cache.clone();
             match proto {
                 Protocol::Udp => {
                     tokio::spawn(Self::forward_to_servers(
                         nameservers,
+                        upstreams,
+                        strategy,
+                        cache,
                         sender,
                         src_address,
                         req,
@@ -256,79 +435,334 @@ impl CoreDns {
                 }
                 Protocol::Tcp => {
                     // we already spawned a new future when we read the message so there is no need to spawn another one
-                    Self::forward_to_servers(nameservers, sender, src_address, req, proto).await;
+                    Self::forward_to_servers(
+                        nameservers,
+                        upstreams,
+                        strategy,
+                        cache,
+                        sender,
+                        src_address,
+                        req,
+                        proto,
+                    )
+                    .await;
                 }
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn forward_to_servers(
         nameservers: Vec<SocketAddr>,
+        upstreams: Arc<Mutex<Upstreams>>,
+        strategy: ForwardStrategy,
+        cache: Arc<ResponseCache>,
         mut sender: BufDnsStreamHandle,
         src_address: SocketAddr,
         req: Message,
         proto: Protocol,
     ) {
-        let mut timeout = DEFAULT_TIMEOUT;
-        // Remember do not divide by 0.
-        if !nameservers.is_empty() {
-            timeout = Duration::from_secs(5) / nameservers.len() as u32
+        let (ordered, policy) = {
+            let mut upstreams = upstreams.lock().expect("lock upstreams");
+            (
+                upstreams.order(nameservers.clone()),
+                upstreams.policy.clone(),
+            )
+        };
+        let forwarder = Forwarder {
+            nameservers: &ordered,
+            upstreams: &upstreams,
+            policy: &policy,
+            strategy,
+            proto,
+        };
+        let deadline = Instant::now() + forwarder.total_timeout();
+        if let Some(resp) = forwarder.forward(&req, deadline).await {
+            cache.insert(&req, &nameservers, &resp);
+            reply(&mut sender, src_address, &resp, req.edns.as_ref(), proto);
         }
-        // forward dns request to hosts's /etc/resolv.conf
-        for addr in nameservers {
-            let (client, handle) = match proto {
-                Protocol::Udp => {
-                    let stream = UdpClientStream::builder(addr, TokioRuntimeProvider::default())
-                        .with_timeout(Some(timeout))
-                        .build();
-                    let (cl, bg) = Client::<TokioRuntimeProvider>::from_sender(stream);
-                    let handle = tokio::spawn(bg);
-                    (cl, handle)
-                }
-                Protocol::Tcp => {
-                    let (stream, sender) = TcpClientStream::new(
-                        addr,
-                        None,
-                        Some(timeout),
-                        TokioRuntimeProvider::default(),
-                    );
+    }
+}
 
-                    let stream = match stream.await {
-                        Ok(a) => a,
-                        Err(e) => {
-                            debug!("Failed to connect to {addr}: {e}");
-                            continue;
-                        }
-                    };
+// Forwards a request to upstream servers and records their health.
+struct Forwarder<'a> {
+    // Servers in the order they are queried.
+    nameservers: &'a [SocketAddr],
+    upstreams: &'a Mutex<Upstreams>,
+    policy: &'a UpstreamPolicy,
+    strategy: ForwardStrategy,
+    proto: Protocol,
+}
 
-                    let (cl, bg) =
-                        Client::<TokioRuntimeProvider>::with_timeout(stream, sender, timeout);
+impl Forwarder<'_> {
+    // The time all attempts of a forwarded query may take together.
+    fn total_timeout(&self) -> Duration {
+        let policy = self.policy;
+        match (policy.timeout, self.strategy) {
+            (Some(timeout), ForwardStrategy::Sequential) => {
+                timeout * self.nameservers.len() as u32 * policy.attempts
+            }
+            (Some(timeout), _) => timeout * policy.attempts,
+            (None, _) => DEFAULT_TIMEOUT,
+        }
+    }
 
-                    let handle = tokio::spawn(bg);
-                    (cl, handle)
+    // Forward the request with the given strategy, all servers are tried as
+    // often as the policy says until one gives a valid answer or the deadline
+    // passes.
+    async fn forward(&self, req: &Message, deadline: Instant) -> Option<Message> {
+        if self.nameservers.is_empty() {
+            return None;
+        }
+        // Without a timeout from resolv.conf all attempts together get the
+        // default timeout.
+        let policy = self.policy;
+        let budget = policy.timeout.unwrap_or(DEFAULT_TIMEOUT / policy.attempts);
+
+        let mut fallback = None;
+        for _ in 0..policy.attempts {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            let resp = match self.strategy {
+                ForwardStrategy::Sequential => {
+                    let timeout = policy
+                        .timeout
+                        .unwrap_or(budget / self.nameservers.len() as u32);
+                    self.forward_sequential(req, timeout, deadline).await
+                }
+                ForwardStrategy::Staggered => {
+                    self.forward_racing(req, STAGGER_DELAY, budget.min(remaining))
+                        .await
+                }
+                ForwardStrategy::Parallel => {
+                    self.forward_racing(req, Duration::ZERO, budget.min(remaining))
+                        .await
                 }
             };
+            match resp {
+                Some(resp) if is_valid_answer(&resp) => return Some(resp),
+                Some(resp) => {
+                    fallback.get_or_insert(resp);
+                }
+                None => {}
+            }
+        }
+        fallback
+    }
 
-            if let Some(resp) = forward_dns_req(client, req.clone()).await {
-                if reply(&mut sender, src_address, &resp).is_some() {
-                    // request resolved from following resolver so
-                    // break and don't try other resolvers
-                    break;
+    // Try the servers one after another with the given timeout each, until
+    // the deadline passes.
+    async fn forward_sequential(
+        &self,
+        req: &Message,
+        timeout: Duration,
+        deadline: Instant,
+    ) -> Option<Message> {
+        // forward dns request to hosts's /etc/resolv.conf
+        for addr in self.nameservers {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            let timeout = timeout.min(remaining);
+            if let Some(resp) = self.query_server(*addr, req.clone(), timeout).await {
+                // request resolved from following resolver so
+                // don't try other resolvers
+                return Some(resp);
+            }
+        }
+        None
+    }
+
+    // Query the servers in order, each one `delay` after the previous one
+    // without waiting for it to fail, and return the first valid answer within
+    // the budget. Error answers are only returned if no server gives a valid
+    // one.
+    async fn forward_racing(
+        &self,
+        req: &Message,
+        delay: Duration,
+        budget: Duration,
+    ) -> Option<Message> {
+        let mut queries: FuturesUnordered<_> = self
+            .nameservers
+            .iter()
+            .enumerate()
+            .map(|(i, addr)| (delay * i as u32, *addr))
+            .take_while(|(start, _)| *start < budget)
+            .map(|(start, addr)| {
+                let req = req.clone();
+                async move {
+                    tokio::time::sleep(start).await;
+                    self.query_server(addr, req, budget - start).await
+                }
+            })
+            .collect();
+
+        let mut fallback = None;
+        while let Some(resp) = queries.next().await {
+            match resp {
+                Some(resp) if is_valid_answer(&resp) => return Some(resp),
+                Some(resp) => {
+                    fallback.get_or_insert(resp);
                 }
+                None => {}
             }
-            handle.abort();
         }
+        fallback
     }
+
+    // Forward the request to a single server and record its health, error
+    // answers count as failures.
+    async fn query_server(
+        &self,
+        addr: SocketAddr,
+        req: Message,
+        timeout: Duration,
+    ) -> Option<Message> {
+        let start = Instant::now();
+        let resp = query_server(addr, req, self.proto, timeout).await;
+        self.upstreams.lock().expect("lock upstreams").record(
+            addr,
+            resp.as_ref()
+                .filter(|r| is_valid_answer(r))
+                .map(|_| start.elapsed()),
+        );
+        resp
+    }
+}
+
+// Whether an upstream answer can be used, servers failing or refusing the
+// query may be misconfigured so other servers are preferred.
+fn is_valid_answer(msg: &Message) -> bool {
+    matches!(
+        msg.response_code,
+        ResponseCode::NoError | ResponseCode::NXDomain
+    )
+}
+
+// Periodically query the upstream servers considered dead so they are
+// preferred again once they recover.
+pub async fn probe_upstreams(upstreams: Arc<Mutex<Upstreams>>) {
+    let mut interval = tokio::time::interval(PROBE_INTERVAL);
+    loop {
+        interval.tick().await;
+        let dead = upstreams.lock().expect("lock upstreams").dead_servers();
+        for addr in dead {
+            let mut probe = Message::new(0, MessageType::Query, OpCode::Query);
+            probe.add_query(Query::query(Name::root(), RecordType::NS));
+            let start = Instant::now();
+            let resp = query_server(addr, probe, Protocol::Udp, PROBE_TIMEOUT).await;
+            upstreams
+                .lock()
+                .expect("lock upstreams")
+                .record(addr, resp.filter(is_valid_answer).map(|_| start.elapsed()));
+        }
+    }
+}
+
+// Forward the request to a single server.
+async fn query_server(
+    addr: SocketAddr,
+    req: Message,
+    proto: Protocol,
+    timeout: Duration,
+) -> Option<Message> {
+    let (client, handle) = match proto {
+        Protocol::Udp => {
+            let stream = UdpClientStream::builder(addr, TokioRuntimeProvider::default())
+                .with_timeout(Some(timeout))
+                .build();
+            let (cl, bg) = Client::<TokioRuntimeProvider>::from_sender(stream);
+            let handle = tokio::spawn(bg);
+            (cl, handle)
+        }
+        Protocol::Tcp => {
+            let (stream, sender) =
+                TcpClientStream::new(addr, None, Some(timeout), TokioRuntimeProvider::default());
+
+            let stream = match stream.await {
+                Ok(a) => a,
+                Err(e) => {
+                    debug!("Failed to connect to {addr}: {e}");
+                    return None;
+                }
+            };
+
+            let (cl, bg) = Client::<TokioRuntimeProvider>::with_timeout(stream, sender, timeout);
+
+            let handle = tokio::spawn(bg);
+            (cl, handle)
+        }
+    };
+
+    let resp = forward_dns_req(client, req).await;
+    handle.abort();
+    resp
+}
+
+// Send the response to the client. The OPT record is only added if the request
+// had one, and UDP responses which do not fit into the payload size of the
+// client are truncated so the client retries over TCP.
+fn reply(
+    sender: &mut BufDnsStreamHandle,
+    socket_addr: SocketAddr,
+    msg: &Message,
+    req_edns: Option<&Edns>,
+    proto: Protocol,
+) -> Option<()> {
+    send(
+        sender,
+        socket_addr,
+        &response(msg, req_edns),
+        req_edns,
+        proto,
+    )
 }
 
-fn reply(sender: &mut BufDnsStreamHandle, socket_addr: SocketAddr, msg: &Message) -> Option<()> {
-    let id = msg.id;
+// Turn a message into the response sent to the client.
+fn response(msg: &Message, req_edns: Option<&Edns>) -> Message {
     let mut msg_mut = msg.clone().into_response();
     // If `RD` is set and `RA` is false set `RA`.
     if msg.recursion_desired && !msg.recursion_available {
         msg_mut.metadata.recursion_available = true;
     }
-    let response = SerialMessage::new(msg_mut.to_vec().ok()?, socket_addr);
+
+    // Never pass on the OPT record of upstream servers, answer with our own.
+    msg_mut.edns = req_edns.map(|req_edns| {
+        let mut edns = Edns::new();
+        edns.set_max_payload(EDNS_MAX_PAYLOAD);
+        edns.set_dnssec_ok(req_edns.flags().dnssec_ok);
+        edns
+    });
+    msg_mut
+}
+
+// Send a response built with `response`.
+fn send(
+    sender: &mut BufDnsStreamHandle,
+    socket_addr: SocketAddr,
+    msg_mut: &Message,
+    req_edns: Option<&Edns>,
+    proto: Protocol,
+) -> Option<()> {
+    let id = msg_mut.id;
+    let max_size = match proto {
+        Protocol::Udp => req_edns.map_or(512, |e| e.max_payload().clamp(512, EDNS_MAX_PAYLOAD)),
+        Protocol::Tcp => u16::MAX,
+    };
+
+    let mut bytes = msg_mut.to_vec().ok()?;
+    if bytes.len() > max_size as usize {
+        debug!(
+            "[{id}] response size {} exceeds {max_size} bytes, truncating",
+            bytes.len()
+        );
+        bytes = msg_mut.clone().truncate().to_vec().ok()?;
+    }
+    let response = SerialMessage::new(bytes, socket_addr);
 
     match sender.send(response) {
         Ok(_) => {
@@ -342,36 +776,91 @@ fn reply(sender: &mut BufDnsStreamHandle, socket_addr: SocketAddr, msg: &Message
     Some(())
 }
 
-fn parse_dns_msg(body: SerialMessage) -> Option<(Name, RecordType, Message)> {
-    match Message::from_vec(body.bytes()) {
-        Ok(msg) => {
-            let mut name = Name::default();
-            let mut record_type: RecordType = RecordType::A;
+fn parse_dns_msg(body: SerialMessage) -> ParsedMessage {
+    let bytes = body.bytes();
+    let msg = match Message::from_vec(bytes) {
+        Ok(msg) => msg,
+        Err(e) => {
+            warn!("Failed while parsing message: {e}");
+            // If we can at least read the header of a query tell the client
+            // the message was malformed so it does not wait for a timeout.
+            if bytes.len() >= 12 && bytes[2] & 0x80 == 0 {
+                let id = u16::from_be_bytes([bytes[0], bytes[1]]);
+                let op_code = OpCode::from_u8((bytes[2] >> 3) & 0x0f);
+                return ParsedMessage::Error(Message::error_msg(
+                    id,
+                    op_code,
+                    ResponseCode::FormErr,
+                ));
+            }
+            return ParsedMessage::Ignore;
+        }
+    };
 
-            let parsed_msg = format!(
-                "[{}] parsed message body: {} edns: {}",
-                msg.id,
-                msg.queries
-                    .first()
-                    .map(|q| {
-                        name = q.name().clone();
-                        record_type = q.query_type();
-
-                        format!("{} {} {}", q.name(), q.query_type(), q.query_class(),)
-                    })
-                    .unwrap_or_else(Default::default,),
-                msg.edns.is_some(),
-            );
+    debug!(
+        "parsed message {:?}",
+        format!(
+            "[{}] parsed message body: {} edns: {}",
+            msg.id,
+            msg.queries
+                .iter()
+                .map(|q| format!("{} {} {}", q.name(), q.query_type(), q.query_class()))
+                .collect::<Vec<String>>()
+                .join(", "),
+            msg.edns.is_some(),
+        )
+    );
+
+    // Never answer responses, this could cause loops.
+    if msg.message_type != MessageType::Query {
+        debug!("[{}] ignoring message which is not a query", msg.id);
+        return ParsedMessage::Ignore;
+    }
 
-            debug!("parsed message {parsed_msg:?}");
+    if msg.op_code != OpCode::Query && msg.op_code != OpCode::Update {
+        debug!("[{}] opcode {} is not implemented", msg.id, msg.op_code);
+        return ParsedMessage::Error(error_response(&msg, ResponseCode::NotImp));
+    }
 
-            Some((name, record_type, msg))
+    // We only implement EDNS version 0.
+    if msg.edns.as_ref().is_some_and(|edns| edns.version() > 0) {
+        debug!("[{}] unsupported EDNS version", msg.id);
+        return ParsedMessage::Error(error_response(&msg, ResponseCode::BADVERS));
+    }
+
+    // We only support exactly one question per message, like most servers do.
+    let (name, record_type) = match msg.queries.as_slice() {
+        [query] => (query.name().clone(), query.query_type()),
+        queries => {
+            debug!(
+                "[{}] rejecting message with {} questions",
+                msg.id,
+                queries.len()
+            );
+            return ParsedMessage::Error(error_response(&msg, ResponseCode::FormErr));
         }
-        Err(e) => {
-            warn!("Failed while parsing message: {e}");
-            None
+    };
+
+    // The zone section of an update has the same format as the question
+    // section, the zone type must be SOA (RFC 2136 section 2.3).
+    if msg.op_code == OpCode::Update {
+        if record_type != RecordType::SOA {
+            debug!("[{}] invalid update zone type {record_type}", msg.id);
+            return ParsedMessage::Error(error_response(&msg, ResponseCode::FormErr));
         }
+        return ParsedMessage::Update(name, msg, bytes.to_vec());
     }
+
+    ParsedMessage::Query(name, record_type, msg)
+}
+
+// Build an error response for the given request.
+fn error_response(req: &Message, response_code: ResponseCode) -> Message {
+    let mut msg = Message::error_msg(req.id, req.op_code, response_code);
+    msg.add_queries(req.queries.iter().cloned());
+    msg.metadata.recursion_desired = req.recursion_desired;
+    msg.edns = req.edns.clone();
+    msg
 }
 
 async fn forward_dns_req(cl: Client<TokioRuntimeProvider>, message: Message) -> Option<Message> {
@@ -407,93 +896,999 @@ async fn forward_dns_req(cl: Client<TokioRuntimeProvider>, message: Message) ->
 
 fn reply_ptr(
     name: &str,
+    network_name: &str,
     backend: &Guard<Arc<DNSBackend>>,
     src_address: SocketAddr,
     req: &Message,
 ) -> Option<Message> {
-    let ptr_lookup_ip: String;
-    // Are we IPv4 or IPv6?
+    // neither ipv4 or ipv6, something we do not understand
+    let lookup_ip = reverse_name_to_ip(name)?;
+
+    trace!("Performing reverse lookup for ip: {}", &lookup_ip);
 
-    match name.strip_suffix(".in-addr.arpa.") {
-        Some(n) => ptr_lookup_ip = n.split('.').rev().collect::<Vec<&str>>().join("."),
+    let mut names = if backend.ptr_all_aliases {
+        backend.reverse_lookup(&src_address.ip(), &lookup_ip)?
+    } else {
+        backend.reverse_lookup_first(&src_address.ip(), &lookup_ip)?
+    };
+    // Wildcard names cannot be the result of a reverse lookup.
+    names.retain(|n| !n.starts_with("*."));
+    if backend.cname_aliases {
+        // Only return the container name when aliases are answered as CNAME.
+        names.truncate(1);
+    } else if backend.ptr_short_id {
+        // The short container id is returned after the names so it can be
+        // resolved again.
+        if let Some(id) = backend.reverse_lookup_id(&src_address.ip(), &lookup_ip) {
+            if !names.contains(&id) {
+                names.push(id);
+            }
+        }
+    }
+
+    let ttl = backend.ttl(network_name);
+    let mut req_clone = req.clone();
+    for entry in names {
+        // Answer with fully qualified names so they can be resolved again.
+        if let Ok(answer) = Name::from_ascii(backend.fqdn(network_name, &entry)) {
+            let record = Record::<RData>::from_rdata(
+                Name::from_str_relaxed(name).unwrap_or_default(),
+                ttl,
+                RData::PTR(rdata::PTR(answer)),
+            );
+            req_clone.add_answer(record);
+        }
+    }
+    Some(req_clone)
+}
+
+fn reply_ip(
+    name: &str,
+    request_name: &Name,
+    network_name: &str,
+    record_type: RecordType,
+    backend: &Guard<Arc<DNSBackend>>,
+    src_address: SocketAddr,
+    req: &Message,
+) -> Option<Message> {
+    // attempt intra network resolution, names with a CNAME are answered with
+    // the CNAME followed by the addresses of the target if it is known.
+    let (owner, cname, resolved_ip_list) =
+        match cname_target(name, network_name, backend, src_address) {
+            Some(target) => {
+                let target_name = Name::from_ascii(&target).ok()?;
+                let resolved = backend
+                    .lookup(&src_address.ip(), network_name, &target)
+                    .unwrap_or_default();
+                (target_name.clone(), Some(target_name), resolved)
+            }
+            None => (
+                request_name.clone(),
+                None,
+                backend.lookup(&src_address.ip(), network_name, name)?,
+            ),
+        };
+
+    let mut req = req.clone();
+    if let Some(target) = cname {
+        req.add_answer(Record::<RData>::from_rdata(
+            request_name.clone(),
+            backend.record_ttl(&src_address.ip(), network_name, name, RecordType::CNAME),
+            RData::CNAME(rdata::CNAME(target)),
+        ));
+    }
+    let ttl = backend.record_ttl(
+        &src_address.ip(),
+        network_name,
+        &owner.to_string(),
+        record_type,
+    );
+
+    let mut addrs: Vec<IpAddr> = resolved_ip_list
+        .into_iter()
+        .filter(|ip| match record_type {
+            RecordType::A => ip.is_ipv4(),
+            RecordType::AAAA => ip.is_ipv6(),
+            _ => false,
+        })
+        .collect();
+    backend.order_answers(
+        network_name,
+        &format!("{}/{}", owner.to_lowercase(), record_type),
+        &mut addrs,
+    );
+
+    for record_addr in addrs {
+        let rdata = match record_addr {
+            IpAddr::V4(ipv4) => RData::A(rdata::A(ipv4)),
+            IpAddr::V6(ipv6) => RData::AAAA(rdata::AAAA(ipv6)),
+        };
+        req.add_answer(Record::<RData>::from_rdata(owner.clone(), ttl, rdata));
+    }
+    Some(req)
+}
+
+// Returns the fully qualified CNAME target of a name, either of a static CNAME
+// record or the container name of an alias when aliases are answered as CNAME.
+fn cname_target(
+    name: &str,
+    network_name: &str,
+    backend: &DNSBackend,
+    src_address: SocketAddr,
+) -> Option<String> {
+    if let Some(target) = backend.lookup_cname(&src_address.ip(), network_name, name) {
+        return Some(target);
+    }
+    backend
+        .lookup_canonical(&src_address.ip(), network_name, name)
+        .map(|canonical| backend.fqdn(network_name, &canonical))
+}
+
+// A name with a CNAME, static or an alias answered as CNAME, has no other
+// records (RFC 1034), the CNAME is the answer for all types. A and AAAA
+// answers add the addresses of the target.
+fn answers_with_cname(
+    record_type: RecordType,
+    name: &str,
+    network_name: &str,
+    backend: &DNSBackend,
+    src_address: SocketAddr,
+) -> bool {
+    record_type != RecordType::A
+        && record_type != RecordType::AAAA
+        && cname_target(name, network_name, backend, src_address).is_some()
+}
+
+fn reply_cname(
+    name: &str,
+    request_name: &Name,
+    network_name: &str,
+    backend: &Guard<Arc<DNSBackend>>,
+    src_address: SocketAddr,
+    req: &Message,
+) -> Option<Message> {
+    let mut req_clone = req.clone();
+    match cname_target(name, network_name, backend, src_address) {
+        Some(target) => {
+            req_clone.add_answer(Record::<RData>::from_rdata(
+                request_name.clone(),
+                backend.record_ttl(&src_address.ip(), network_name, name, RecordType::CNAME),
+                RData::CNAME(rdata::CNAME(Name::from_ascii(&target).ok()?)),
+            ));
+            add_address_glue(&mut req_clone, &target, network_name, backend, src_address);
+        }
+        // The name exists but has no CNAME, return an empty answer.
         None => {
-            // not ipv4
-            match name.strip_suffix(".ip6.arpa.") {
-                Some(n) => {
-                    // ipv6 string is 39 chars max
-                    let mut tmp_ip = String::with_capacity(40);
-                    for (i, c) in n.split('.').rev().enumerate() {
-                        tmp_ip.push_str(c);
-                        // insert colon after 4 hex chars but not at the end
-                        if i % 4 == 3 && i < 31 {
-                            tmp_ip.push(':');
-                        }
-                    }
-                    ptr_lookup_ip = tmp_ip;
-                }
-                // neither ipv4 or ipv6, something we do not understand
-                None => return None,
+            backend.lookup(&src_address.ip(), network_name, name)?;
+        }
+    }
+    Some(req_clone)
+}
+
+fn reply_srv(
+    name: &str,
+    request_name: &Name,
+    network_name: &str,
+    backend: &Guard<Arc<DNSBackend>>,
+    src_address: SocketAddr,
+    req: &Message,
+) -> Option<Message> {
+    let services = backend.lookup_srv(&src_address.ip(), network_name, name)?;
+    let ttl = backend.ttl(network_name);
+
+    let mut req_clone = req.clone();
+    let mut targets: Vec<&str> = Vec::new();
+    for service in &services {
+        let target = match Name::from_ascii(backend.fqdn(network_name, &service.target)) {
+            Ok(t) => t,
+            Err(e) => {
+                error!("Invalid SRV target {}: {e}", service.target);
+                continue;
             }
+        };
+        let record = Record::<RData>::from_rdata(
+            request_name.clone(),
+            ttl,
+            RData::SRV(rdata::SRV::new(
+                service.priority,
+                service.weight,
+                service.port,
+                target,
+            )),
+        );
+        req_clone.add_answer(record);
+        if !targets.contains(&service.target.as_str()) {
+            targets.push(&service.target);
         }
     }
 
-    trace!("Performing reverse lookup for ip: {}", &ptr_lookup_ip);
+    // Add the addresses of the targets to the additional section so clients
+    // do not need another round trip.
+    for target in targets {
+        add_address_glue(
+            &mut req_clone,
+            &backend.fqdn(network_name, target),
+            network_name,
+            backend,
+            src_address,
+        );
+    }
 
-    // We should probably log malformed queries, but for now if-let should be fine.
-    if let Ok(lookup_ip) = ptr_lookup_ip.parse() {
-        if let Some(reverse_lookup) = backend.reverse_lookup(&src_address.ip(), &lookup_ip) {
-            let mut req_clone = req.clone();
-            for entry in reverse_lookup {
-                if let Ok(answer) = Name::from_ascii(format!("{entry}.")) {
-                    let record = Record::<RData>::from_rdata(
-                        Name::from_str_relaxed(name).unwrap_or_default(),
-                        0,
-                        RData::PTR(rdata::PTR(answer)),
-                    );
-                    req_clone.add_answer(record);
-                }
+    Some(req_clone)
+}
+
+fn reply_mx(
+    name: &str,
+    request_name: &Name,
+    network_name: &str,
+    backend: &Guard<Arc<DNSBackend>>,
+    src_address: SocketAddr,
+    req: &Message,
+) -> Option<Message> {
+    let exchanges = backend.lookup_mx(&src_address.ip(), network_name, name)?;
+    let ttl = backend.ttl(network_name);
+
+    let mut req_clone = req.clone();
+    for mx in &exchanges {
+        let exchange = match Name::from_ascii(backend.fqdn(network_name, &mx.exchange)) {
+            Ok(e) => e,
+            Err(e) => {
+                error!("Invalid MX exchange {}: {e}", mx.exchange);
+                continue;
             }
-            return Some(req_clone);
+        };
+        let record = Record::<RData>::from_rdata(
+            request_name.clone(),
+            ttl,
+            RData::MX(rdata::MX::new(mx.preference, exchange)),
+        );
+        req_clone.add_answer(record);
+        add_address_glue(
+            &mut req_clone,
+            &backend.fqdn(network_name, &mx.exchange),
+            network_name,
+            backend,
+            src_address,
+        );
+    }
+
+    Some(req_clone)
+}
+
+fn reply_txt(
+    name: &str,
+    request_name: &Name,
+    network_name: &str,
+    backend: &Guard<Arc<DNSBackend>>,
+    src_address: SocketAddr,
+    req: &Message,
+) -> Option<Message> {
+    let containers = backend
+        .lookup_containers(&src_address.ip(), network_name, name)
+        .unwrap_or_default();
+    let static_txts = backend
+        .lookup_txt(&src_address.ip(), network_name, name)
+        .unwrap_or_default();
+    if containers.is_empty() && static_txts.is_empty() {
+        return None;
+    }
+    let ttl = backend.ttl(network_name);
+
+    let mut req_clone = req.clone();
+    let txt_ttl = backend.record_ttl(&src_address.ip(), network_name, name, RecordType::TXT);
+    for txt in static_txts {
+        let record = Record::<RData>::from_rdata(
+            request_name.clone(),
+            txt_ttl,
+            RData::TXT(rdata::TXT::new(split_txt(&txt))),
+        );
+        req_clone.add_answer(record);
+    }
+    for (network, ctr) in containers {
+        // One record per container and network, each attribute is its own string.
+        let mut txt = vec![format!("id={}", ctr.id), format!("network={network}")];
+        // A single character string is limited to 255 bytes so split long alias lists.
+        let mut aliases = String::from("aliases=");
+        for alias in &ctr.aliases {
+            if aliases.len() + alias.len() + 1 > 255 {
+                txt.push(aliases);
+                aliases = String::from("aliases=");
+            }
+            if !aliases.ends_with('=') {
+                aliases.push(',');
+            }
+            aliases.push_str(alias);
         }
-    };
-    None
+        txt.push(aliases);
+
+        let record = Record::<RData>::from_rdata(
+            request_name.clone(),
+            ttl,
+            RData::TXT(rdata::TXT::new(txt)),
+        );
+        req_clone.add_answer(record);
+    }
+
+    Some(req_clone)
 }
 
-fn reply_ip<'a>(
+// Split a TXT value into character strings of at most 255 bytes.
+fn split_txt(value: &str) -> Vec<String> {
+    let mut strings = vec![String::new()];
+    for c in value.chars() {
+        if strings.last().is_some_and(|s| s.len() + c.len_utf8() > 255) {
+            strings.push(String::new());
+        }
+        if let Some(s) = strings.last_mut() {
+            s.push(c);
+        }
+    }
+    strings
+}
+
+// Answer SOA and NS queries for the search domain and reverse zones. Other
+// names in the zones are handled by the caller which answers NODATA or
+// NXDOMAIN with the SOA.
+fn reply_zone(
     name: &str,
     request_name: &Name,
     network_name: &str,
     record_type: RecordType,
     backend: &Guard<Arc<DNSBackend>>,
+    req: &Message,
+) -> Option<Message> {
+    let zone = backend.authoritative_zone(network_name, name)?;
+    if !name.trim_end_matches('.').eq_ignore_ascii_case(&zone) {
+        return None;
+    }
+
+    let ttl = backend.ttl(network_name);
+    let mut req_clone = req.clone();
+    match record_type {
+        RecordType::SOA => {
+            req_clone.add_answer(soa_record(backend, network_name, &zone)?);
+        }
+        RecordType::NS => {
+            let ns = Name::from_ascii(backend.fqdn(network_name, NAMESERVER_NAME)).ok()?;
+            req_clone.add_answer(Record::<RData>::from_rdata(
+                request_name.clone(),
+                ttl,
+                RData::NS(rdata::NS(ns.clone())),
+            ));
+            for ip in backend.listen_ips.get(network_name).into_iter().flatten() {
+                let data = match ip {
+                    IpAddr::V4(ipv4) => RData::A(rdata::A(*ipv4)),
+                    IpAddr::V6(ipv6) => RData::AAAA(rdata::AAAA(*ipv6)),
+                };
+                req_clone.add_additional(Record::<RData>::from_rdata(ns.clone(), ttl, data));
+            }
+        }
+        _ => {}
+    }
+
+    Some(req_clone)
+}
+
+// Answer a query for a name in a zone loaded from a zone file.
+fn reply_zone_file(
+    zone: &Zone,
+    request_name: &Name,
+    record_type: RecordType,
+    req: &Message,
+) -> Message {
+    let mut req_clone = req.clone();
+    req_clone.metadata.authoritative = true;
+    match zone.lookup(request_name, record_type) {
+        ZoneAnswer::Answer(answers, additionals) => {
+            for record in answers {
+                req_clone.add_answer(record);
+            }
+            for record in additionals {
+                req_clone.add_additional(record);
+            }
+        }
+        ZoneAnswer::NoData => {
+            req_clone.add_authorities(zone.negative_soa());
+        }
+        ZoneAnswer::NxDomain => {
+            req_clone.metadata.response_code = ResponseCode::NXDomain;
+            req_clone.add_authorities(zone.negative_soa());
+        }
+    }
+    req_clone
+}
+
+// Apply a dynamic update (RFC 2136) of a search domain. Signed updates must
+// be signed with one of the configured TSIG keys, unsigned ones are only
+// accepted from allowed addresses. Returns the response and the verified
+// request if the response must be signed.
+fn reply_update<'a>(
+    zone: &Name,
+    network_name: &str,
+    backend: &'a Guard<Arc<DNSBackend>>,
     src_address: SocketAddr,
-    req: &'a mut Message,
-) -> Option<&'a Message> {
-    // attempt intra network resolution
-    let resolved_ip_list = backend.lookup(&src_address.ip(), network_name, name)?;
-
-    if record_type == RecordType::A {
-        for record_addr in resolved_ip_list {
-            if let IpAddr::V4(ipv4) = record_addr {
-                // Set TTL to 0 which means client should not cache it.
-                // Containers can be be restarted with a different ip at any time so allowing
-                // caches here doesn't make much sense given the server is local and queries
-                // should be fast enough anyway.
-                let record =
-                    Record::<RData>::from_rdata(request_name.clone(), 0, RData::A(rdata::A(ipv4)));
-                req.add_answer(record);
-            }
-        }
-    } else if record_type == RecordType::AAAA {
-        for record_addr in resolved_ip_list {
-            if let IpAddr::V6(ipv6) = record_addr {
-                let record = Record::<RData>::from_rdata(
-                    request_name.clone(),
-                    0,
-                    RData::AAAA(rdata::AAAA(ipv6)),
-                );
-                req.add_answer(record);
+    req: &Message,
+    bytes: &[u8],
+) -> (Message, Option<TsigRequest<'a>>) {
+    let signed = if tsig::is_signed(req) {
+        match tsig::verify_request(&backend.tsig_keys, bytes) {
+            Ok(signed) => Some(signed),
+            Err(e) => {
+                debug!("Rejecting update from {src_address} with invalid TSIG: {e:?}");
+                return (error_response(req, ResponseCode::NotAuth), None);
             }
         }
+    } else if backend.update_allowed(&src_address.ip()) {
+        None
+    } else {
+        debug!("Refusing unsigned update from {src_address}");
+        return (error_response(req, ResponseCode::Refused), None);
+    };
+
+    let zone_name = zone.to_string();
+    if !backend.is_search_domain(network_name, &zone_name) {
+        debug!("Rejecting update of {zone_name} which is not a search domain");
+        return (error_response(req, ResponseCode::NotAuth), signed);
+    }
+    let code = backend.apply_update(
+        &src_address.ip(),
+        network_name,
+        &zone_name.trim_end_matches('.').to_lowercase(),
+        &req.answers,
+        &req.authorities,
+    );
+    debug!("Update of {zone_name} from {src_address}: {code}");
+    (error_response(req, code), signed)
+}
+
+// Answer a zone transfer request for a search domain or a zone file zone. The
+// transfer contains the records visible to the requester and is split into
+// several messages if needed.
+fn reply_axfr(
+    name: &str,
+    request_name: &Name,
+    network_name: &str,
+    backend: &Guard<Arc<DNSBackend>>,
+    src_address: SocketAddr,
+    req: &Message,
+    proto: Protocol,
+) -> Vec<Message> {
+    // Zone transfers are only defined over TCP (RFC 5936).
+    if let Protocol::Udp = proto {
+        return vec![error_response(req, ResponseCode::FormErr)];
+    }
+    if !backend.transfer_allowed(&src_address.ip()) {
+        debug!("Refusing zone transfer of {name} to {src_address}");
+        return vec![error_response(req, ResponseCode::Refused)];
+    }
+    let records = match axfr_records(name, request_name, network_name, backend, src_address, req) {
+        Some(records) => records,
+        None => return vec![error_response(req, ResponseCode::NotAuth)],
+    };
+
+    let mut messages = Vec::new();
+    let mut msg = req.clone();
+    msg.metadata.authoritative = true;
+    let mut size = 0;
+    for record in records {
+        let len = record.to_bytes().map_or(0, |b| b.len());
+        if size + len > AXFR_MESSAGE_SIZE && !msg.answers.is_empty() {
+            messages.push(msg);
+            msg = req.clone();
+            msg.metadata.authoritative = true;
+            size = 0;
+        }
+        size += len;
+        msg.add_answer(record);
+    }
+    messages.push(msg);
+    messages
+}
+
+// Collect all records of the zone for a transfer, starting and ending with the
+// SOA. Returns None if the name is not a zone served by us.
+fn axfr_records(
+    name: &str,
+    request_name: &Name,
+    network_name: &str,
+    backend: &Guard<Arc<DNSBackend>>,
+    src_address: SocketAddr,
+    req: &Message,
+) -> Option<Vec<Record>> {
+    if let Some(zone) = backend.lookup_zone(network_name, request_name) {
+        if zone.origin != *request_name {
+            return None;
+        }
+        let soa = zone.soa()?.clone();
+        let mut records = vec![soa.clone()];
+        records.extend(zone.records().cloned());
+        records.push(soa);
+        return Some(records);
+    }
+
+    if !backend.is_search_domain(network_name, name) {
+        return None;
+    }
+    let zone = name.trim_end_matches('.').to_lowercase();
+    let soa = soa_record(backend, network_name, &zone)?;
+    let mut records = vec![soa.clone()];
+
+    // Build the records with the same functions answering queries so the
+    // transfer matches what the requester can resolve.
+    push_answers(
+        &mut records,
+        reply_zone(
+            name,
+            request_name,
+            network_name,
+            RecordType::NS,
+            backend,
+            req,
+        ),
+        request_name,
+    );
+    push_answers(
+        &mut records,
+        reply_mx(name, request_name, network_name, backend, src_address, req),
+        request_name,
+    );
+    for entry in backend.zone_names(&src_address.ip(), network_name, &zone) {
+        let owner_name = format!("{entry}.{zone}.");
+        let owner = match Name::from_ascii(&owner_name) {
+            Ok(n) => n,
+            Err(e) => {
+                debug!("Skipping invalid name {owner_name} in zone transfer: {e}");
+                continue;
+            }
+        };
+        for record_type in [RecordType::A, RecordType::AAAA] {
+            let msg = reply_ip(
+                &owner_name,
+                &owner,
+                network_name,
+                record_type,
+                backend,
+                src_address,
+                req,
+            );
+            push_answers(&mut records, msg, &owner);
+        }
+        let answers = [
+            reply_cname(&owner_name, &owner, network_name, backend, src_address, req),
+            reply_srv(&owner_name, &owner, network_name, backend, src_address, req),
+            reply_mx(&owner_name, &owner, network_name, backend, src_address, req),
+            reply_txt(&owner_name, &owner, network_name, backend, src_address, req),
+        ];
+        for msg in answers {
+            push_answers(&mut records, msg, &owner);
+        }
+    }
+
+    records.push(soa);
+    Some(records)
+}
+
+// Add the answers of the given message owned by the name to the records of a
+// zone transfer, skipping duplicates.
+fn push_answers(records: &mut Vec<Record>, msg: Option<Message>, owner: &Name) {
+    for record in msg.into_iter().flat_map(|m| m.answers) {
+        if record.name == *owner && !records.contains(&record) {
+            records.push(record);
+        }
+    }
+}
+
+// Build the SOA record of the given zone.
+fn soa_record(backend: &DNSBackend, network_name: &str, zone: &str) -> Option<Record> {
+    let apex = Name::from_ascii(format!("{zone}.")).ok()?;
+    let mname = Name::from_ascii(backend.fqdn(network_name, NAMESERVER_NAME)).ok()?;
+    let rname = Name::from_ascii(backend.fqdn(network_name, "hostmaster")).ok()?;
+
+    // Negative answers may be cached as long as local records.
+    let ttl = backend.ttl(network_name);
+    Some(Record::<RData>::from_rdata(
+        apex,
+        ttl,
+        RData::SOA(rdata::SOA::new(
+            mname,
+            rname,
+            backend.serial,
+            SOA_REFRESH,
+            SOA_RETRY,
+            SOA_EXPIRE,
+            ttl,
+        )),
+    ))
+}
+
+// Add the SOA of the zone to the authority section, used for negative
+// answers so clients can cache them.
+fn add_soa_authority(msg: &mut Message, backend: &DNSBackend, network_name: &str, zone: &str) {
+    if let Some(soa) = soa_record(backend, network_name, zone) {
+        msg.add_authority(soa);
+    }
+}
+
+// Add A and AAAA records for the given fully qualified name to the additional
+// section.
+fn add_address_glue(
+    msg: &mut Message,
+    target: &str,
+    network_name: &str,
+    backend: &Guard<Arc<DNSBackend>>,
+    src_address: SocketAddr,
+) {
+    let name = match Name::from_ascii(target) {
+        Ok(n) => n,
+        Err(_) => return,
+    };
+    let ttl = backend.ttl(network_name);
+    if let Some(addrs) = backend.lookup(&src_address.ip(), network_name, target) {
+        for addr in addrs {
+            let data = match addr {
+                IpAddr::V4(ipv4) => RData::A(rdata::A(ipv4)),
+                IpAddr::V6(ipv6) => RData::AAAA(rdata::AAAA(ipv6)),
+            };
+            msg.add_additional(Record::<RData>::from_rdata(name.clone(), ttl, data));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_proto::op::Query;
+
+    fn serial(msg: &Message) -> SerialMessage {
+        SerialMessage::new(msg.to_vec().unwrap(), "127.0.0.1:53".parse().unwrap())
+    }
+
+    fn query(queries: usize) -> Message {
+        let mut msg = Message::new(1, MessageType::Query, OpCode::Query);
+        for _ in 0..queries {
+            msg.add_query(Query::query(
+                Name::from_ascii("web.dns.podman.").unwrap(),
+                RecordType::A,
+            ));
+        }
+        msg
+    }
+
+    fn expect_error(parsed: ParsedMessage, code: ResponseCode) {
+        match parsed {
+            ParsedMessage::Error(msg) => assert_eq!(msg.response_code, code),
+            _ => panic!("expected error response"),
+        }
+    }
+
+    #[test]
+    fn test_parse_dns_msg_single_question() {
+        match parse_dns_msg(serial(&query(1))) {
+            ParsedMessage::Query(name, record_type, _) => {
+                assert_eq!(name.to_string(), "web.dns.podman.");
+                assert_eq!(record_type, RecordType::A);
+            }
+            _ => panic!("expected valid query"),
+        }
+    }
+
+    #[test]
+    fn test_parse_dns_msg_question_count() {
+        expect_error(parse_dns_msg(serial(&query(0))), ResponseCode::FormErr);
+        expect_error(parse_dns_msg(serial(&query(2))), ResponseCode::FormErr);
+    }
+
+    #[test]
+    fn test_parse_dns_msg_opcode() {
+        let mut msg = query(1);
+        msg.metadata.op_code = OpCode::Status;
+        expect_error(parse_dns_msg(serial(&msg)), ResponseCode::NotImp);
+    }
+
+    #[test]
+    fn test_parse_dns_msg_edns_version() {
+        let mut msg = query(1);
+        let mut edns = Edns::new();
+        edns.set_version(1);
+        msg.set_edns(edns);
+        match parse_dns_msg(serial(&msg)) {
+            ParsedMessage::Error(resp) => {
+                assert_eq!(resp.response_code, ResponseCode::BADVERS);
+                // BADVERS needs the OPT record to carry the extended code.
+                assert!(resp.edns.is_some());
+            }
+            _ => panic!("expected error response"),
+        }
+
+        msg.edns.as_mut().unwrap().set_version(0);
+        assert!(matches!(
+            parse_dns_msg(serial(&msg)),
+            ParsedMessage::Query(..)
+        ));
+    }
+
+    #[test]
+    fn test_split_txt() {
+        assert_eq!(split_txt("license=abc"), vec!["license=abc"]);
+        let long = "x".repeat(300);
+        assert_eq!(split_txt(&long), vec!["x".repeat(255), "x".repeat(45)]);
+        // Multibyte characters are not split.
+        let long = "ä".repeat(200);
+        let strings = split_txt(&long);
+        assert_eq!(strings.len(), 2);
+        assert_eq!(strings[0].len(), 254);
+        assert_eq!(strings.concat(), long);
+    }
+
+    #[test]
+    fn test_parse_dns_msg_malformed() {
+        let mut bytes = query(1).to_vec().unwrap();
+        bytes.truncate(14);
+        let parsed = parse_dns_msg(SerialMessage::new(bytes, "127.0.0.1:53".parse().unwrap()));
+        expect_error(parsed, ResponseCode::FormErr);
+
+        // Responses and short garbage are dropped.
+        let msg = query(1).into_response();
+        assert!(matches!(parse_dns_msg(serial(&msg)), ParsedMessage::Ignore));
+        assert!(matches!(
+            parse_dns_msg(SerialMessage::new(
+                vec![1, 2, 3],
+                "127.0.0.1:53".parse().unwrap()
+            )),
+            ParsedMessage::Ignore
+        ));
+    }
+
+    // Start an upstream server answering every query with the given code, or
+    // never answering at all.
+    async fn upstream(code: Option<ResponseCode>) -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0; 512];
+            while let Ok((len, src)) = socket.recv_from(&mut buf).await {
+                if let (Some(code), Ok(req)) = (code, Message::from_vec(&buf[..len])) {
+                    let mut resp = req.into_response();
+                    resp.metadata.response_code = code;
+                    socket.send_to(&resp.to_vec().unwrap(), src).await.unwrap();
+                }
+            }
+        });
+        addr
+    }
+
+    fn forwarder<'a>(
+        nameservers: &'a [SocketAddr],
+        upstreams: &'a Mutex<Upstreams>,
+        policy: &'a UpstreamPolicy,
+        strategy: ForwardStrategy,
+    ) -> Forwarder<'a> {
+        Forwarder {
+            nameservers,
+            upstreams,
+            policy,
+            strategy,
+            proto: Protocol::Udp,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_forward_racing() {
+        let silent = upstream(None).await;
+        let servfail = upstream(Some(ResponseCode::ServFail)).await;
+        let nxdomain = upstream(Some(ResponseCode::NXDomain)).await;
+        let upstreams = Mutex::new(Upstreams::default());
+        let policy = UpstreamPolicy::default();
+        let req = query(1);
+
+        // A silent first server does not delay the answer.
+        let start = std::time::Instant::now();
+        let resp = forwarder(
+            &[silent, nxdomain],
+            &upstreams,
+            &policy,
+            ForwardStrategy::Staggered,
+        )
+        .forward_racing(&req, STAGGER_DELAY, DEFAULT_TIMEOUT)
+        .await
+        .unwrap();
+        assert_eq!(resp.response_code, ResponseCode::NXDomain);
+        assert!(start.elapsed() < Duration::from_secs(2));
+
+        // Valid answers are preferred over errors, which are only returned if
+        // no server answers better.
+        let resp = forwarder(
+            &[servfail, nxdomain],
+            &upstreams,
+            &policy,
+            ForwardStrategy::Parallel,
+        )
+        .forward_racing(&req, Duration::ZERO, DEFAULT_TIMEOUT)
+        .await
+        .unwrap();
+        assert_eq!(resp.response_code, ResponseCode::NXDomain);
+        let resp = forwarder(&[servfail], &upstreams, &policy, ForwardStrategy::Parallel)
+            .forward_racing(&req, Duration::ZERO, DEFAULT_TIMEOUT)
+            .await
+            .unwrap();
+        assert_eq!(resp.response_code, ResponseCode::ServFail);
+        // Error answers are failures, however fast they are.
+        let health = upstreams.lock().unwrap().health(&servfail);
+        assert_eq!(health.successes, 0);
+        assert!(health.failures > 0);
+        assert!(health.rtt.is_none());
+        assert!(
+            forwarder(&[], &upstreams, &policy, ForwardStrategy::Parallel)
+                .forward_racing(&req, Duration::ZERO, DEFAULT_TIMEOUT)
+                .await
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forward_health() {
+        let silent = upstream(None).await;
+        let nxdomain = upstream(Some(ResponseCode::NXDomain)).await;
+        let upstreams = Mutex::new(Upstreams::new(
+            vec![silent, nxdomain],
+            UpstreamPolicy::default(),
+        ));
+        let policy = UpstreamPolicy {
+            timeout: Some(Duration::from_millis(200)),
+            ..Default::default()
+        };
+        let req = query(1);
+
+        for _ in 0..2 {
+            let ordered = upstreams.lock().unwrap().order(vec![silent, nxdomain]);
+            assert_eq!(ordered[0], silent);
+            let deadline = Instant::now() + DEFAULT_TIMEOUT;
+            let resp = forwarder(&ordered, &upstreams, &policy, ForwardStrategy::Sequential)
+                .forward(&req, deadline)
+                .await
+                .unwrap();
+            assert_eq!(resp.response_code, ResponseCode::NXDomain);
+        }
+
+        // The silent server timed out twice and is now queried last.
+        let mut upstreams = upstreams.into_inner().unwrap();
+        assert_eq!(upstreams.dead_servers(), vec![silent]);
+        assert_eq!(upstreams.health(&silent).failures, 2);
+        assert_eq!(upstreams.health(&nxdomain).successes, 2);
+        assert!(upstreams.health(&nxdomain).rtt.is_some());
+        assert_eq!(
+            upstreams.order(vec![silent, nxdomain]),
+            vec![nxdomain, silent]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forward_deadline() {
+        let silent = upstream(None).await;
+        let upstreams = Mutex::new(Upstreams::default());
+        let policy = UpstreamPolicy {
+            timeout: Some(Duration::from_millis(200)),
+            attempts: 3,
+            ..Default::default()
+        };
+        let req = query(1);
+
+        // The attempts stop when the deadline passes.
+        let servers = [silent];
+        let forwarder = forwarder(&servers, &upstreams, &policy, ForwardStrategy::Sequential);
+        assert_eq!(forwarder.total_timeout(), Duration::from_millis(600));
+        let start = std::time::Instant::now();
+        let deadline = Instant::now() + Duration::from_millis(300);
+        assert!(forwarder.forward(&req, deadline).await.is_none());
+        assert!(start.elapsed() < Duration::from_millis(500));
+        assert_eq!(upstreams.lock().unwrap().health(&silent).failures, 2);
+    }
+
+    #[test]
+    fn test_reply_ptr() {
+        let ptrs =
+            |cname_aliases: bool, ptr_all_aliases: bool, ptr_short_id: bool| -> Vec<String> {
+                let options = crate::config::ConfigOptions {
+                    search_domains: vec!["dns.podman".to_string()],
+                    cname_aliases,
+                    ptr_all_aliases,
+                    ptr_short_id,
+                    ..Default::default()
+                };
+                let backend =
+                    crate::config::parse_configs("src/test/config/podman_reverse", &options)
+                        .unwrap()
+                        .0;
+                let backend = ArcSwap::from_pointee(backend);
+                let client: SocketAddr = "10.88.0.4:5353".parse().unwrap();
+                reply_ptr(
+                    "2.0.88.10.in-addr.arpa.",
+                    "net1",
+                    &backend.load(),
+                    client,
+                    &query(1),
+                )
+                .unwrap()
+                .answers
+                .iter()
+                .map(|r| r.data.to_string())
+                .collect()
+            };
+
+        // Every alias on the first shared network is answered fully
+        // qualified.
+        let names = ptrs(false, false, false);
+        assert!(
+            names == vec!["web.dns.podman.", "www.dns.podman.", "api.dns.podman."]
+                || names == vec!["web.dns.podman.", "frontend.dns.podman."],
+            "unexpected names {:?}",
+            names
+        );
+        assert_eq!(ptrs(true, false, false), vec!["web.dns.podman."]);
+
+        // With ptr_all_aliases the aliases of all shared networks.
+        let mut names = ptrs(false, true, false);
+        assert_eq!(names[0], "web.dns.podman.");
+        names.sort();
+        assert_eq!(
+            names,
+            vec![
+                "api.dns.podman.",
+                "frontend.dns.podman.",
+                "web.dns.podman.",
+                "www.dns.podman.",
+            ]
+        );
+        assert_eq!(ptrs(true, true, false), vec!["web.dns.podman."]);
+
+        // The short id is only answered when enabled, after the names.
+        let mut names = ptrs(false, true, true);
+        assert_eq!(names.len(), 5);
+        assert_eq!(names.pop().unwrap(), "68fb291b0318.dns.podman.");
+        assert_eq!(ptrs(true, true, true), vec!["web.dns.podman."]);
+    }
+
+    #[test]
+    fn test_answers_with_cname() {
+        let options = crate::config::ConfigOptions {
+            search_domains: vec!["dns.podman".to_string()],
+            cname_aliases: true,
+            ..Default::default()
+        };
+        let backend = crate::config::parse_configs("src/test/config/podman_reverse", &options)
+            .unwrap()
+            .0;
+        let client: SocketAddr = "10.88.0.4:5353".parse().unwrap();
+
+        // Aliases are answered with the CNAME for every type, the canonical
+        // name with its own records.
+        for record_type in [
+            RecordType::CNAME,
+            RecordType::MX,
+            RecordType::TXT,
+            RecordType::SRV,
+        ] {
+            assert!(answers_with_cname(
+                record_type,
+                "www.dns.podman.",
+                "net1",
+                &backend,
+                client
+            ));
+            assert!(!answers_with_cname(
+                record_type,
+                "web.dns.podman.",
+                "net1",
+                &backend,
+                client
+            ));
+        }
+        assert!(!answers_with_cname(
+            RecordType::A,
+            "www.dns.podman.",
+            "net1",
+            &backend,
+            client
+        ));
     }
-    Some(req)
 }
@@ -1 +1,4 @@
+pub mod cache;
 pub mod coredns;
+pub mod tsig;
+pub mod upstream;
@@ -0,0 +1,311 @@
+use crate::error::{AardvarkError, AardvarkResult};
+use hickory_proto::{
+    op::{Header, Message},
+    rr::{
+        rdata::tsig::{make_tsig_record, TsigAlgorithm, TSIG},
+        DNSClass, Name, RData, Record, RecordType,
+    },
+    serialize::binary::{BinDecodable, BinDecoder, BinEncodable, BinEncoder},
+};
+use ring::hmac;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Allowed clock difference of signed messages in seconds, as recommended by
+// RFC 8945.
+const TSIG_FUDGE: u16 = 300;
+
+// A shared secret used to authenticate messages with TSIG (RFC 8945).
+#[derive(Debug, Clone)]
+pub struct TsigKey {
+    pub name: Name,
+    pub algorithm: TsigAlgorithm,
+    secret: Vec<u8>,
+}
+
+// Why a signed request was rejected.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TsigFailure {
+    // The message is not signed.
+    Unsigned,
+    // The key or its algorithm is unknown.
+    BadKey,
+    // The MAC does not match.
+    BadSig,
+    // The signing time is outside of the allowed fudge.
+    BadTime,
+}
+
+// A verified request, needed to sign the response.
+#[derive(Debug)]
+pub struct TsigRequest<'a> {
+    pub key: &'a TsigKey,
+    mac: Vec<u8>,
+}
+
+// Keys are given like for nsupdate -y: [algorithm:]name:base64 secret, the
+// algorithm defaults to hmac-sha256.
+impl FromStr for TsigKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let (algorithm, name, secret) = match parts.as_slice() {
+            [name, secret] => ("hmac-sha256", *name, *secret),
+            [algorithm, name, secret] => (*algorithm, *name, *secret),
+            _ => {
+                return Err(format!(
+                    "invalid TSIG key {s}, must be [algorithm:]name:secret"
+                ))
+            }
+        };
+        let algorithm = match algorithm.to_lowercase().as_str() {
+            "hmac-sha256" => TsigAlgorithm::HmacSha256,
+            "hmac-sha384" => TsigAlgorithm::HmacSha384,
+            "hmac-sha512" => TsigAlgorithm::HmacSha512,
+            _ => {
+                return Err(format!(
+                    "unsupported TSIG algorithm {algorithm}, must be one of hmac-sha256, hmac-sha384 or hmac-sha512"
+                ))
+            }
+        };
+        // Key names are always compared fully qualified.
+        let mut name = Name::from_ascii(name)
+            .map_err(|e| format!("invalid TSIG key name {name}: {e}"))?
+            .to_lowercase();
+        name.set_fqdn(true);
+        let secret = data_encoding::BASE64
+            .decode(secret.as_bytes())
+            .map_err(|e| format!("invalid TSIG secret of key {name}: {e}"))?;
+        Ok(TsigKey {
+            name,
+            algorithm,
+            secret,
+        })
+    }
+}
+
+impl TsigKey {
+    fn hmac_key(&self) -> hmac::Key {
+        let algorithm = match self.algorithm {
+            TsigAlgorithm::HmacSha384 => hmac::HMAC_SHA384,
+            TsigAlgorithm::HmacSha512 => hmac::HMAC_SHA512,
+            _ => hmac::HMAC_SHA256,
+        };
+        hmac::Key::new(algorithm, &self.secret)
+    }
+
+    fn mac(&self, data: &[u8]) -> Vec<u8> {
+        hmac::sign(&self.hmac_key(), data).as_ref().to_vec()
+    }
+
+    fn verify(&self, data: &[u8], mac: &[u8]) -> bool {
+        hmac::verify(&self.hmac_key(), data, mac).is_ok()
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+// Verify the TSIG record of a request with one of the given keys. The MAC is
+// computed over the raw message as received, the TSIG record must be the last
+// additional record.
+pub fn verify_request<'a>(
+    keys: &'a [TsigKey],
+    bytes: &[u8],
+) -> Result<TsigRequest<'a>, TsigFailure> {
+    let mut decoder = BinDecoder::new(bytes);
+    let mut header = Header::read(&mut decoder).map_err(|_| TsigFailure::Unsigned)?;
+    if header.counts.additionals == 0 {
+        return Err(TsigFailure::Unsigned);
+    }
+    // Skip everything up to the last record.
+    for _ in 0..header.counts.queries {
+        hickory_proto::op::Query::read(&mut decoder).map_err(|_| TsigFailure::Unsigned)?;
+    }
+    let records = header.counts.answers as usize
+        + header.counts.authorities as usize
+        + header.counts.additionals as usize
+        - 1;
+    for _ in 0..records {
+        Record::<RData>::read(&mut decoder).map_err(|_| TsigFailure::Unsigned)?;
+    }
+    let signed_len = bytes.len() - decoder.len();
+    let record = Record::<RData>::read(&mut decoder).map_err(|_| TsigFailure::Unsigned)?;
+    if record.dns_class != DNSClass::ANY {
+        return Err(TsigFailure::Unsigned);
+    }
+    let key_name = record.name;
+    let tsig = match record.data {
+        RData::TSIG(tsig) => tsig,
+        _ => return Err(TsigFailure::Unsigned),
+    };
+
+    let key = keys
+        .iter()
+        .find(|k| k.name == key_name && k.algorithm == tsig.algorithm)
+        .ok_or(TsigFailure::BadKey)?;
+
+    // The MAC covers the message with the original id and without the TSIG
+    // record followed by the TSIG variables.
+    header.metadata.id = tsig.oid;
+    header.counts.additionals -= 1;
+    let mut tbs = Vec::with_capacity(bytes.len());
+    let mut encoder = BinEncoder::new(&mut tbs);
+    header.emit(&mut encoder).map_err(|_| TsigFailure::BadSig)?;
+    encoder
+        .emit_vec(&bytes[12..signed_len])
+        .map_err(|_| TsigFailure::BadSig)?;
+    tsig.emit_tsig_for_mac(&mut encoder, &key_name)
+        .map_err(|_| TsigFailure::BadSig)?;
+    if !key.verify(&tbs, &tsig.mac) {
+        return Err(TsigFailure::BadSig);
+    }
+    // The fudge of the client is capped so a replayed request can not extend
+    // its own validity.
+    if now().abs_diff(tsig.time) > tsig.fudge.min(TSIG_FUDGE) as u64 {
+        return Err(TsigFailure::BadTime);
+    }
+
+    Ok(TsigRequest { key, mac: tsig.mac })
+}
+
+// Sign a response to a verified request. The message must not be changed
+// afterwards.
+pub fn sign_response(request: &TsigRequest, msg: &mut Message) -> AardvarkResult<()> {
+    let tsig = TSIG::new(
+        request.key.algorithm.clone(),
+        now(),
+        TSIG_FUDGE,
+        Vec::new(),
+        msg.id,
+        None,
+        Vec::new(),
+    );
+
+    // The MAC covers the request MAC, the response and the TSIG variables.
+    let mut tbs = Vec::new();
+    let mut encoder = BinEncoder::new(&mut tbs);
+    let to_err = |e| AardvarkError::msg(format!("failed to sign response: {e}"));
+    encoder.emit_u16(request.mac.len() as u16).map_err(to_err)?;
+    encoder.emit_vec(&request.mac).map_err(to_err)?;
+    msg.emit(&mut encoder).map_err(to_err)?;
+    tsig.emit_tsig_for_mac(&mut encoder, &request.key.name)
+        .map_err(to_err)?;
+
+    let mac = request.key.mac(&tbs);
+    let record = make_tsig_record(request.key.name.clone(), tsig.set_mac(mac));
+    msg.signature = Some(Box::new(record));
+    Ok(())
+}
+
+// Whether the message carries a TSIG record. Depending on the features of
+// hickory-proto the parsed record is either the last additional record or the
+// signature of the message.
+pub fn is_signed(msg: &Message) -> bool {
+    msg.signature.is_some()
+        || msg
+            .additionals
+            .last()
+            .is_some_and(|r| r.record_type() == RecordType::TSIG)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hickory_proto::op::{MessageType, OpCode};
+
+    // Sign a request like a client does, the MAC covers the message and the
+    // TSIG variables.
+    fn sign_request(key: &TsigKey, msg: &mut Message, time: u64, fudge: u16) {
+        let tsig = TSIG::new(
+            key.algorithm.clone(),
+            time,
+            fudge,
+            Vec::new(),
+            msg.id,
+            None,
+            Vec::new(),
+        );
+        let mut tbs = msg.to_vec().unwrap();
+        let mut encoder = BinEncoder::new(&mut tbs);
+        encoder.set_offset(encoder.len());
+        tsig.emit_tsig_for_mac(&mut encoder, &key.name).unwrap();
+        let mac = key.mac(&tbs);
+        msg.signature = Some(Box::new(make_tsig_record(
+            key.name.clone(),
+            tsig.set_mac(mac),
+        )));
+    }
+
+    #[test]
+    fn test_parse_tsig_key() {
+        let key: TsigKey = "update-key:c2VjcmV0".parse().unwrap();
+        assert_eq!(key.name, Name::from_ascii("update-key.").unwrap());
+        assert_eq!(key.algorithm, TsigAlgorithm::HmacSha256);
+        assert_eq!(key.secret, b"secret");
+        let key: TsigKey = "HMAC-SHA512:update-key.:c2VjcmV0".parse().unwrap();
+        assert_eq!(key.algorithm, TsigAlgorithm::HmacSha512);
+
+        assert!("update-key".parse::<TsigKey>().is_err());
+        assert!("hmac-md5:update-key:c2VjcmV0".parse::<TsigKey>().is_err());
+        assert!("update-key:not base64".parse::<TsigKey>().is_err());
+    }
+
+    #[test]
+    fn test_verify_request() {
+        let key: TsigKey = "update-key:c2VjcmV0".parse().unwrap();
+        let other: TsigKey = "other-key:c2VjcmV0".parse().unwrap();
+        let keys = [key.clone()];
+        let msg = Message::new(7, MessageType::Query, OpCode::Update);
+        assert_eq!(
+            verify_request(&keys, &msg.to_vec().unwrap()).unwrap_err(),
+            TsigFailure::Unsigned
+        );
+
+        let mut signed = msg.clone();
+        sign_request(&key, &mut signed, now(), TSIG_FUDGE);
+        let bytes = signed.to_vec().unwrap();
+        let request = verify_request(&keys, &bytes).unwrap();
+        assert_eq!(request.key.name, key.name);
+        assert!(is_signed(&Message::from_vec(&bytes).unwrap()));
+        assert!(is_signed(&signed));
+        assert!(!is_signed(&msg));
+
+        // Tampered messages, unknown keys and old signatures are rejected.
+        let mut tampered = bytes.clone();
+        tampered[2] ^= 1;
+        assert_eq!(
+            verify_request(&keys, &tampered).unwrap_err(),
+            TsigFailure::BadSig
+        );
+        assert_eq!(
+            verify_request(&[other], &bytes).unwrap_err(),
+            TsigFailure::BadKey
+        );
+        let mut old = msg.clone();
+        sign_request(&key, &mut old, now() - 3600, TSIG_FUDGE);
+        assert_eq!(
+            verify_request(&keys, &old.to_vec().unwrap()).unwrap_err(),
+            TsigFailure::BadTime
+        );
+        // A larger fudge of the client does not make old signatures valid.
+        let mut old = msg.clone();
+        sign_request(&key, &mut old, now() - 3600, u16::MAX);
+        assert_eq!(
+            verify_request(&keys, &old.to_vec().unwrap()).unwrap_err(),
+            TsigFailure::BadTime
+        );
+
+        // Responses are signed with the key of the request.
+        let mut response = msg.into_response();
+        sign_response(&request, &mut response).unwrap();
+        assert!(is_signed(
+            &Message::from_vec(&response.to_vec().unwrap()).unwrap()
+        ));
+    }
+}
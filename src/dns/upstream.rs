@@ -0,0 +1,256 @@
+use log::{debug, info};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+// Number of failed queries in a row after which a server is considered dead.
+const DEAD_AFTER_FAILURES: u32 = 2;
+
+// How the upstream servers are queried, parsed from the options of the host's
+// resolv.conf so forwarded queries behave like the glibc resolver.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamPolicy {
+    // Start with the next server for every query (options rotate).
+    pub rotate: bool,
+    // Timeout of a single query (options timeout:n). Without it the forward
+    // timeout is split between all servers and attempts.
+    pub timeout: Option<Duration>,
+    // How often all servers are tried (options attempts:n).
+    pub attempts: u32,
+}
+
+impl Default for UpstreamPolicy {
+    fn default() -> Self {
+        UpstreamPolicy {
+            rotate: false,
+            timeout: None,
+            attempts: 1,
+        }
+    }
+}
+
+// Health of an upstream server based on the queries forwarded to it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ServerHealth {
+    // Queries answered.
+    pub successes: u64,
+    // Queries which timed out or failed otherwise.
+    pub failures: u64,
+    // Failed queries since the last answer.
+    pub consecutive_failures: u32,
+    // Smoothed round trip time of the answers.
+    pub rtt: Option<Duration>,
+}
+
+impl ServerHealth {
+    // A dead server is only queried after all others until it answers again.
+    pub fn is_dead(&self) -> bool {
+        self.consecutive_failures >= DEAD_AFTER_FAILURES
+    }
+}
+
+// The upstream servers of the host and the policy to query them, shared by
+// all listeners and replaced when resolv.conf changes. It also tracks the
+// health of every server queries are forwarded to.
+#[derive(Debug, Default)]
+pub struct Upstreams {
+    pub nameservers: Vec<SocketAddr>,
+    pub policy: UpstreamPolicy,
+    // DNS servers of the containers and networks, their health is tracked
+    // like the one of the host's servers.
+    custom: Vec<SocketAddr>,
+    // Position of the first server of the next query with options rotate.
+    rotation: usize,
+    health: HashMap<SocketAddr, ServerHealth>,
+}
+
+impl Upstreams {
+    pub fn new(nameservers: Vec<SocketAddr>, policy: UpstreamPolicy) -> Upstreams {
+        Upstreams {
+            nameservers,
+            policy,
+            ..Default::default()
+        }
+    }
+
+    // Take the servers and policy of a reloaded resolv.conf, the health of
+    // servers which are still used is kept. Returns whether the servers or the
+    // policy changed.
+    pub fn reload(&mut self, upstreams: Upstreams) -> bool {
+        let changed = self.nameservers != upstreams.nameservers || self.policy != upstreams.policy;
+        self.nameservers = upstreams.nameservers;
+        self.policy = upstreams.policy;
+        self.prune();
+        changed
+    }
+
+    // Set the DNS servers of the containers and networks after the config was
+    // reloaded.
+    pub fn set_custom_servers(&mut self, servers: Vec<SocketAddr>) {
+        self.custom = servers;
+        self.prune();
+    }
+
+    fn is_configured(&self, addr: &SocketAddr) -> bool {
+        self.nameservers.contains(addr) || self.custom.contains(addr)
+    }
+
+    // Forget the health of servers which are no longer configured.
+    fn prune(&mut self) {
+        let health = std::mem::take(&mut self.health);
+        self.health = health
+            .into_iter()
+            .filter(|(addr, _)| self.is_configured(addr))
+            .collect();
+    }
+
+    // Returns the servers in the order they are queried. Without options
+    // rotate the fastest server is preferred, servers without answers yet come
+    // first so their round trip time is measured. With options rotate every
+    // query starts one server further than the previous one. Dead servers are
+    // always queried last.
+    pub fn order(&mut self, mut nameservers: Vec<SocketAddr>) -> Vec<SocketAddr> {
+        if self.policy.rotate && nameservers.len() > 1 {
+            let start = self.rotation % nameservers.len();
+            nameservers.rotate_left(start);
+            self.rotation = self.rotation.wrapping_add(1);
+        } else {
+            nameservers.sort_by_key(|addr| self.health(addr).rtt.unwrap_or_default());
+        }
+        nameservers.sort_by_key(|addr| self.health(addr).is_dead());
+        nameservers
+    }
+
+    // The health of the server, servers never queried are healthy.
+    pub fn health(&self, addr: &SocketAddr) -> ServerHealth {
+        self.health.get(addr).cloned().unwrap_or_default()
+    }
+
+    // Record the result of a query to the server, the round trip time if it
+    // answered.
+    pub fn record(&mut self, addr: SocketAddr, rtt: Option<Duration>) {
+        let health = self.health.entry(addr).or_default();
+        let was_dead = health.is_dead();
+        match rtt {
+            Some(rtt) => {
+                health.successes += 1;
+                health.consecutive_failures = 0;
+                // Smooth like BIND does so a single slow answer does not
+                // change the preferred server.
+                health.rtt = Some(match health.rtt {
+                    Some(srtt) => srtt * 7 / 10 + rtt * 3 / 10,
+                    None => rtt,
+                });
+                if was_dead {
+                    info!("Upstream server {addr} is answering again");
+                }
+            }
+            None => {
+                health.failures += 1;
+                health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+                if !was_dead && health.is_dead() {
+                    info!(
+                        "Upstream server {addr} failed {} queries in a row, using other servers first",
+                        health.consecutive_failures
+                    );
+                }
+            }
+        }
+        debug!("Upstream server {addr} health: {health:?}");
+    }
+
+    // Configured servers which are considered dead and must be probed.
+    pub fn dead_servers(&self) -> Vec<SocketAddr> {
+        self.health
+            .iter()
+            .filter(|(addr, health)| health.is_dead() && self.is_configured(addr))
+            .map(|(addr, _)| *addr)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_order() {
+        let servers: Vec<SocketAddr> = vec![
+            "192.0.2.1:53".parse().unwrap(),
+            "192.0.2.2:53".parse().unwrap(),
+            "192.0.2.3:53".parse().unwrap(),
+        ];
+        let mut upstreams = Upstreams::new(servers.clone(), UpstreamPolicy::default());
+        assert_eq!(upstreams.order(servers.clone()), servers);
+        assert_eq!(upstreams.order(servers.clone()), servers);
+
+        upstreams.policy.rotate = true;
+        let firsts: Vec<SocketAddr> = (0..4)
+            .map(|_| upstreams.order(servers.clone())[0])
+            .collect();
+        assert_eq!(firsts, vec![servers[0], servers[1], servers[2], servers[0]]);
+    }
+
+    #[test]
+    fn test_health() {
+        let fast: SocketAddr = "192.0.2.1:53".parse().unwrap();
+        let slow: SocketAddr = "192.0.2.2:53".parse().unwrap();
+        let dead: SocketAddr = "192.0.2.3:53".parse().unwrap();
+        let new: SocketAddr = "192.0.2.4:53".parse().unwrap();
+        let mut upstreams = Upstreams::new(vec![dead, slow, fast], UpstreamPolicy::default());
+        let ms = Duration::from_millis;
+
+        upstreams.record(slow, Some(ms(80)));
+        upstreams.record(fast, Some(ms(10)));
+        upstreams.record(fast, Some(ms(20)));
+        upstreams.record(dead, None);
+        assert!(!upstreams.health(&dead).is_dead());
+        upstreams.record(dead, None);
+        assert_eq!(
+            upstreams.health(&dead),
+            ServerHealth {
+                successes: 0,
+                failures: 2,
+                consecutive_failures: 2,
+                rtt: None,
+            }
+        );
+        assert_eq!(upstreams.health(&fast).rtt, Some(ms(13)));
+        assert_eq!(upstreams.dead_servers(), vec![dead]);
+
+        // Servers without answers are measured first, then the fastest is
+        // preferred and dead servers come last.
+        assert_eq!(
+            upstreams.order(vec![dead, slow, fast, new]),
+            vec![new, fast, slow, dead]
+        );
+        upstreams.policy.rotate = true;
+        assert_eq!(
+            upstreams.order(vec![dead, slow, fast]),
+            vec![slow, fast, dead]
+        );
+
+        // Health is kept over reloads and a dead server recovers with its
+        // next answer.
+        assert!(upstreams.reload(Upstreams::new(vec![dead], UpstreamPolicy::default())));
+        assert!(!upstreams.reload(Upstreams::new(vec![dead], UpstreamPolicy::default())));
+        assert!(upstreams.health(&dead).is_dead());
+        upstreams.record(dead, Some(ms(30)));
+        assert!(!upstreams.health(&dead).is_dead());
+        assert!(upstreams.dead_servers().is_empty());
+
+        // Servers which are no longer configured are forgotten and not
+        // probed.
+        assert_eq!(upstreams.health(&fast), ServerHealth::default());
+        upstreams.set_custom_servers(vec![slow]);
+        upstreams.record(new, None);
+        upstreams.record(new, None);
+        upstreams.record(slow, None);
+        upstreams.record(slow, None);
+        assert_eq!(upstreams.dead_servers(), vec![slow]);
+        upstreams.set_custom_servers(Vec::new());
+        assert!(upstreams.dead_servers().is_empty());
+        assert_eq!(upstreams.health(&slow), ServerHealth::default());
+        assert_eq!(upstreams.health(&new), ServerHealth::default());
+    }
+}
@@ -1,9 +1,14 @@
 use std::env;
+use std::net::IpAddr;
 use std::str::FromStr;
 
 use clap::{Parser, Subcommand};
 
+use aardvark_dns::backend::{AnswerOrder, ForwardStrategy};
 use aardvark_dns::commands::{run, version};
+use aardvark_dns::config::ConfigOptions;
+use aardvark_dns::dns::tsig::TsigKey;
+use ipnet::IpNet;
 use log::Level;
 use syslog::{BasicLogger, Facility, Formatter3164};
 
@@ -16,9 +21,44 @@ struct Opts {
     /// Host port for aardvark servers, defaults to 5533
     #[clap(short, long)]
     port: Option<u16>,
-    /// Filters search domain for backward compatiblity with dnsname/dnsmasq
+    /// Filters search domain for backward compatiblity with dnsname/dnsmasq, several domains
+    /// can be given comma separated
     #[clap(short, long)]
     filter_search_domain: Option<String>,
+    /// Answer aliases as CNAME records pointing to the container name
+    #[clap(long)]
+    cname_aliases: bool,
+    /// Answer reverse lookups with the names of the container on all shared networks, not only
+    /// on the first one
+    #[clap(long)]
+    ptr_all_aliases: bool,
+    /// Answer reverse lookups with the short container id after the container names
+    #[clap(long)]
+    ptr_short_id: bool,
+    /// Order of answers for names with several addresses: stable, round-robin or shuffle
+    #[clap(long, default_value = "stable")]
+    answer_order: AnswerOrder,
+    /// TTL in seconds of answers for container names, defaults to 0
+    #[clap(long, default_value_t = 0)]
+    ttl: u32,
+    /// Names resolving to the gateway of the network, comma separated, e.g. host.containers.internal
+    #[clap(long, value_delimiter = ',')]
+    gateway_name: Vec<String>,
+    /// Subnets allowed to request zone transfers (AXFR) besides the host, comma separated
+    #[clap(long, value_delimiter = ',', value_parser = parse_subnet)]
+    axfr_allow: Vec<IpNet>,
+    /// Subnets allowed to send dynamic updates (RFC 2136) besides the host, comma separated
+    #[clap(long, value_delimiter = ',', value_parser = parse_subnet)]
+    update_allow: Vec<IpNet>,
+    /// Key for TSIG signed dynamic updates as [algorithm:]name:base64 secret, can be repeated
+    #[clap(long)]
+    tsig_key: Vec<TsigKey>,
+    /// Maximum number of cached answers of the upstream servers, 0 disables the cache
+    #[clap(long, default_value_t = 1000)]
+    cache_size: usize,
+    /// How queries are forwarded to several upstream servers: sequential, staggered or parallel
+    #[clap(long, default_value = "sequential")]
+    forward_strategy: ForwardStrategy,
     /// Aardvark-dns trig command
     #[clap(subcommand)]
     subcmd: SubCommand,
@@ -32,6 +72,13 @@ enum SubCommand {
     Version(version::Version),
 }
 
+// Parse a subnet in CIDR notation or a single ip address.
+fn parse_subnet(s: &str) -> Result<IpNet, String> {
+    IpNet::from_str(s)
+        .or_else(|_| IpAddr::from_str(s).map(IpNet::from))
+        .map_err(|_| format!("invalid subnet or ip address {s}"))
+}
+
 fn main() {
     // Close all fds we inherited from our parent process, we only need stdio.
     let _ = unsafe { libc::close_range(3, libc::c_uint::MAX, 0) };
@@ -72,8 +119,22 @@ fn main() {
     let filter_search_domain = opts
         .filter_search_domain
         .unwrap_or_else(|| String::from(".dns.podman"));
+    let options = ConfigOptions {
+        search_domains: filter_search_domain.split(',').map(String::from).collect(),
+        cname_aliases: opts.cname_aliases,
+        ptr_all_aliases: opts.ptr_all_aliases,
+        ptr_short_id: opts.ptr_short_id,
+        answer_order: opts.answer_order,
+        ttl: opts.ttl,
+        gateway_names: opts.gateway_name,
+        transfer_acl: opts.axfr_allow,
+        update_acl: opts.update_allow,
+        tsig_keys: opts.tsig_key,
+        cache_size: opts.cache_size,
+        forward_strategy: opts.forward_strategy,
+    };
     let result = match opts.subcmd {
-        SubCommand::Run(run) => run.exec(dir, port, filter_search_domain),
+        SubCommand::Run(run) => run.exec(dir, port, options),
         SubCommand::Version(version) => {
             version.exec();
             Ok(())
@@ -1,8 +1,10 @@
 use crate::backend::DNSBackend;
 use crate::config::constants::AARDVARK_PID_FILE;
-use crate::config::parse_configs;
-use crate::dns::coredns::CoreDns;
+use crate::config::{parse_configs, ConfigOptions};
+use crate::dns::cache::ResponseCache;
 use crate::dns::coredns::DNS_PORT;
+use crate::dns::coredns::{probe_upstreams, CoreDns};
+use crate::dns::upstream::{UpstreamPolicy, Upstreams};
 use crate::error::AardvarkError;
 use crate::error::AardvarkErrorList;
 use crate::error::AardvarkResult;
@@ -22,6 +24,7 @@ use std::os::fd::OwnedFd;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::OnceLock;
+use std::time::Duration;
 use tokio::net::{TcpListener, UdpSocket};
 use tokio::signal::unix::{signal, SignalKind};
 use tokio::task::JoinHandle;
@@ -65,7 +68,7 @@ pub fn create_pid(config_path: &str) -> AardvarkResult<()> {
 pub async fn serve(
     config_path: &str,
     port: u16,
-    filter_search_domain: &str,
+    options: &ConfigOptions,
     ready: OwnedFd,
 ) -> AardvarkResult<()> {
     let mut signals = signal(SignalKind::hangup())?;
@@ -73,15 +76,17 @@ pub async fn serve(
 
     let mut handles_v4 = HashMap::new();
     let mut handles_v6 = HashMap::new();
-    let nameservers = Arc::new(Mutex::new(Vec::new()));
+    let upstreams = Arc::new(Mutex::new(Upstreams::default()));
+    let cache = Arc::new(ResponseCache::new(options.cache_size));
 
     read_config_and_spawn(
         config_path,
         port,
-        filter_search_domain,
+        options,
         &mut handles_v4,
         &mut handles_v6,
-        nameservers.clone(),
+        upstreams.clone(),
+        cache.clone(),
         no_proxy,
     )
     .await?;
@@ -92,6 +97,9 @@ pub async fn serve(
     unistd::write(&ready, &msg)?;
     drop(ready);
 
+    // Check dead upstream servers in the background.
+    tokio::spawn(probe_upstreams(upstreams.clone()));
+
     // Setup inotify to monitor resolv.conf
     let mut event_stream = get_inotify_event_stream();
     loop {
@@ -102,10 +110,11 @@ pub async fn serve(
                 if let Err(e) = read_config_and_spawn(
                     config_path,
                     port,
-                    filter_search_domain,
+                    options,
                     &mut handles_v4,
                     &mut handles_v6,
-                    nameservers.clone(),
+                    upstreams.clone(),
+                    cache.clone(),
                     no_proxy,
                 )
                 .await
@@ -123,8 +132,12 @@ pub async fn serve(
                         continue;
                     }
                 };
-                match nameservers.lock() {
-                    Ok(mut ns) => *ns = upstream_resolvers,
+                match upstreams.lock() {
+                    Ok(mut ns) => {
+                        if ns.reload(upstream_resolvers) {
+                            cache.flush();
+                        }
+                    }
                     Err(err) => {
                         error!("Failed to reload nameservers, could not obtain lock: {err}");
                     }
@@ -143,7 +156,8 @@ async fn stop_and_start_threads<Ip>(
     listen_ips: HashMap<String, Vec<Ip>>,
     thread_handles: &mut ThreadHandleMap<Ip>,
     no_proxy: bool,
-    nameservers: Arc<Mutex<Vec<SocketAddr>>>,
+    upstreams: Arc<Mutex<Upstreams>>,
+    cache: Arc<ResponseCache>,
 ) -> AardvarkResult<()>
 where
     Ip: Eq + Hash + Copy + Into<IpAddr> + Send + 'static,
@@ -177,7 +191,8 @@ where
     for (network_name, ip) in to_start {
         let (shutdown_tx, shutdown_rx) = flume::bounded(0);
         let network_name_ = network_name.clone();
-        let ns = nameservers.clone();
+        let ns = upstreams.clone();
+        let cache = cache.clone();
         let addr = SocketAddr::new(ip.into(), port);
         let udp_sock = match UdpSocket::bind(addr).await {
             Ok(s) => s,
@@ -210,6 +225,7 @@ where
                 shutdown_rx,
                 no_proxy,
                 ns,
+                cache,
             )
             .await
         });
@@ -258,6 +274,7 @@ async fn stop_threads<Ip>(
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn start_dns_server(
     name: String,
     udp_socket: UdpSocket,
@@ -265,32 +282,38 @@ async fn start_dns_server(
     backend: &'static ArcSwap<DNSBackend>,
     rx: flume::Receiver<()>,
     no_proxy: bool,
-    nameservers: Arc<Mutex<Vec<SocketAddr>>>,
+    upstreams: Arc<Mutex<Upstreams>>,
+    cache: Arc<ResponseCache>,
 ) -> AardvarkResult<()> {
-    let server = CoreDns::new(name, backend, rx, no_proxy, nameservers);
+    let server = CoreDns::new(name, backend, rx, no_proxy, upstreams, cache);
     server
         .run(udp_socket, tcp_socket)
         .await
         .wrap("run dns server")
 }
 
+#[allow(clippy::too_many_arguments)]
 async fn read_config_and_spawn(
     config_path: &str,
     port: u16,
-    filter_search_domain: &str,
+    options: &ConfigOptions,
     handles_v4: &mut ThreadHandleMap<Ipv4Addr>,
     handles_v6: &mut ThreadHandleMap<Ipv6Addr>,
-    nameservers: Arc<Mutex<Vec<SocketAddr>>>,
+    upstreams: Arc<Mutex<Upstreams>>,
+    cache: Arc<ResponseCache>,
     no_proxy: bool,
 ) -> AardvarkResult<()> {
-    let (conf, listen_ip_v4, listen_ip_v6) =
-        parse_configs(config_path, filter_search_domain).wrap("unable to parse config")?;
+    let (mut conf, listen_ip_v4, listen_ip_v6) =
+        parse_configs(config_path, options).wrap("unable to parse config")?;
 
     // We store the `DNSBackend` in an `ArcSwap` so we can replace it when the configuration is
     // reloaded.
     static DNSBACKEND: OnceLock<ArcSwap<DNSBackend>> = OnceLock::new();
     let backend = match DNSBACKEND.get() {
         Some(b) => {
+            // Keep the records registered with dynamic updates.
+            conf.dynamic_records = b.load().dynamic_records.clone();
+            conf.dynamic_records.retain_networks(&conf.listen_ips);
             b.store(Arc::new(conf));
             b
         }
@@ -328,14 +351,26 @@ async fn read_config_and_spawn(
                 "failed to get upstream nameservers, dns forwarding will not work",
                 err,
             ));
-            Vec::new()
+            Upstreams::default()
         }
     };
     debug!("Using the following upstream servers: {upstream_resolvers:?}");
 
     {
         // use new scope to only lock for a short time
-        *nameservers.lock().expect("lock nameservers") = upstream_resolvers;
+        let custom_servers = backend
+            .load()
+            .custom_dns_servers()
+            .into_iter()
+            .map(|ip| SocketAddr::new(ip, DNS_PORT))
+            .collect();
+        let mut upstreams = upstreams.lock().expect("lock upstreams");
+        // The config is reloaded for every container start and stop, only
+        // drop the cached answers when the host's servers changed.
+        if upstreams.reload(upstream_resolvers) {
+            cache.flush();
+        }
+        upstreams.set_custom_servers(custom_servers);
     }
 
     if let Err(err) = stop_and_start_threads(
@@ -344,7 +379,8 @@ async fn read_config_and_spawn(
         listen_ip_v4,
         handles_v4,
         no_proxy,
-        nameservers.clone(),
+        upstreams.clone(),
+        cache.clone(),
     )
     .await
     {
@@ -357,7 +393,8 @@ async fn read_config_and_spawn(
         listen_ip_v6,
         handles_v6,
         no_proxy,
-        nameservers,
+        upstreams,
+        cache,
     )
     .await
     {
@@ -389,13 +426,16 @@ fn daemonize() -> Result<(), Error> {
     Ok(())
 }
 
-// read /etc/resolv.conf and return all nameservers
-fn get_upstream_resolvers() -> AardvarkResult<Vec<SocketAddr>> {
+// read /etc/resolv.conf and return all nameservers and the options to query them
+fn get_upstream_resolvers() -> AardvarkResult<Upstreams> {
     let mut f = File::open(RESOLV_CONF).wrap("open resolv.conf")?;
     let mut buf = String::with_capacity(4096);
     f.read_to_string(&mut buf).wrap("read resolv.conf")?;
 
-    parse_resolv_conf(&buf)
+    Ok(Upstreams::new(
+        parse_resolv_conf(&buf)?,
+        parse_upstream_policy(&buf),
+    ))
 }
 
 fn get_inotify_event_stream() -> Option<EventStream<[u8; 1024]>> {
@@ -485,6 +525,41 @@ fn parse_resolv_conf(content: &str) -> AardvarkResult<Vec<SocketAddr>> {
     Ok(nameservers)
 }
 
+// Parse the options of resolv.conf which change how the nameservers are
+// queried. Like glibc unknown options are ignored and values are capped to the
+// glibc limits.
+fn parse_upstream_policy(content: &str) -> UpstreamPolicy {
+    let mut policy = UpstreamPolicy::default();
+    for line in content.split('\n') {
+        // split of comments
+        let line = match line.split_once(['#', ';']) {
+            Some((f, _)) => f,
+            None => line,
+        };
+        let mut line_parts = line.split_whitespace();
+        match line_parts.next() {
+            Some("options") => {
+                for option in line_parts {
+                    let (key, value) = match option.split_once(':') {
+                        Some((key, value)) => (key, value.parse::<u32>().ok()),
+                        None => (option, None),
+                    };
+                    match (key, value) {
+                        ("rotate", _) => policy.rotate = true,
+                        ("timeout", Some(n)) => {
+                            policy.timeout = Some(Duration::from_secs(n.clamp(1, 30) as u64))
+                        }
+                        ("attempts", Some(n)) => policy.attempts = n.clamp(1, 5),
+                        _ => {}
+                    }
+                }
+            }
+            _ => continue,
+        }
+    }
+    policy
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -616,4 +691,44 @@ nameserver 1.1.1.2",
         .expect("failed to parse");
         assert_eq!(res, vec![IP_FE80_1]);
     }
+
+    #[test]
+    fn test_parse_upstream_policy() {
+        assert_eq!(
+            parse_upstream_policy("nameserver 1.1.1.1"),
+            UpstreamPolicy::default()
+        );
+
+        let policy = parse_upstream_policy(
+            "nameserver 1.1.1.1
+search example.com corp.example.com.
+options rotate timeout:2
+options attempts:3 ndots:2 edns0 # comment",
+        );
+        assert_eq!(
+            policy,
+            UpstreamPolicy {
+                rotate: true,
+                timeout: Some(Duration::from_secs(2)),
+                attempts: 3,
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_upstream_policy_limits() {
+        // Values are capped like glibc does and invalid ones are ignored.
+        let policy = parse_upstream_policy(
+            "options timeout:100 attempts:0 ndots:20
+options attempts:x",
+        );
+        assert_eq!(
+            policy,
+            UpstreamPolicy {
+                rotate: false,
+                timeout: Some(Duration::from_secs(30)),
+                attempts: 1,
+            }
+        );
+    }
 }
@@ -8,7 +8,7 @@ mod tests {
     use std::collections::HashMap;
     use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-    use aardvark_dns::backend::DNSBackend;
+    use aardvark_dns::backend::{self, AnswerOrder, DNSBackend, MxRecord, SrvRecord};
     use aardvark_dns::config;
     use aardvark_dns::error::AardvarkResult;
     use std::str::FromStr;
@@ -27,6 +27,7 @@ mod tests {
     const IP_FDFD_733B_DC3_220B_3: IpAddr =
         IpAddr::V6(Ipv6Addr::new(0xfdfd, 0x733b, 0xdc3, 0x220b, 0, 0, 0, 3));
 
+    #[allow(clippy::type_complexity)]
     fn parse_configs(
         dir: &str,
     ) -> AardvarkResult<(
@@ -34,7 +35,26 @@ mod tests {
         HashMap<String, Vec<Ipv4Addr>>,
         HashMap<String, Vec<Ipv6Addr>>,
     )> {
-        config::parse_configs(dir, "")
+        config::parse_configs(dir, &config::ConfigOptions::default())
+    }
+
+    // Parse configs with the given search domain.
+    #[allow(clippy::type_complexity)]
+    fn parse_configs_with_search_domain(
+        dir: &str,
+        search_domain: &str,
+    ) -> AardvarkResult<(
+        DNSBackend,
+        HashMap<String, Vec<Ipv4Addr>>,
+        HashMap<String, Vec<Ipv6Addr>>,
+    )> {
+        config::parse_configs(
+            dir,
+            &config::ConfigOptions {
+                search_domains: vec![search_domain.to_string()],
+                ..Default::default()
+            },
+        )
     }
 
     /* -------------------------------------------- */
@@ -280,7 +300,7 @@ mod tests {
                 {
                     Some(lookup_vec) => {
                         assert_eq!(
-                            &vec![
+                            vec![
                                 "trustingzhukovsky".to_string(),
                                 "ctr1".to_string(),
                                 "ctra".to_string()
@@ -308,7 +328,7 @@ mod tests {
                 ) {
                     Some(lookup_vec) => {
                         assert_eq!(
-                            &vec!["test1".to_string(), "7b46c7ad93fc".to_string()],
+                            vec!["test1".to_string(), "7b46c7ad93fc".to_string()],
                             lookup_vec
                         );
                     }
@@ -473,4 +493,1165 @@ mod tests {
             Err(e) => panic!("{}", e),
         }
     }
+
+    /* -------------------------------------------- */
+    // ---------Test aardvark-dns services ---------
+    /* -------------------------------------------- */
+    #[test]
+    // Services must be published for every name of the container
+    // and point to the container name.
+    fn test_lookup_srv_records() {
+        let backend = parse_configs("src/test/config/podman_services")
+            .expect("parse config error")
+            .0;
+        let client: IpAddr = "10.88.0.4".parse().unwrap();
+
+        let res = backend.lookup_srv(&client, "", "_https._tcp.webalias");
+        assert_eq!(
+            res,
+            Some(vec![SrvRecord {
+                priority: 10,
+                weight: 5,
+                port: 443,
+                target: "web".to_string(),
+            }])
+        );
+
+        // Two containers share the web name, both must be returned.
+        let res = backend.lookup_srv(&client, "", "_http._tcp.web.");
+        assert_eq!(
+            res,
+            Some(vec![
+                SrvRecord {
+                    priority: 0,
+                    weight: 0,
+                    port: 80,
+                    target: "web".to_string(),
+                },
+                SrvRecord {
+                    priority: 0,
+                    weight: 0,
+                    port: 8080,
+                    target: "web2".to_string(),
+                }
+            ])
+        );
+
+        assert_eq!(backend.lookup_srv(&client, "", "_http._udp.web"), None);
+        assert_eq!(backend.lookup_srv(&client, "", "_http._tcp.client"), None);
+    }
+    #[test]
+    // A services column must not break the optional DNS servers column
+    fn test_services_with_dns_servers() {
+        let backend = parse_configs("src/test/config/podman_services")
+            .expect("parse config error")
+            .0;
+        assert_eq!(
+            backend.ctr_dns_server[&"10.88.0.3".parse().unwrap()],
+            Some(vec!["8.8.8.8".parse().unwrap()])
+        );
+        assert_eq!(backend.ctr_dns_server[&"10.88.0.2".parse().unwrap()], None);
+    }
+    #[test]
+    // A container name is its own mail exchanger
+    fn test_lookup_mx_container_name() {
+        let backend =
+            parse_configs_with_search_domain("src/test/config/podman_services", "dns.podman")
+                .expect("parse config error")
+                .0;
+        let client: IpAddr = "10.88.0.4".parse().unwrap();
+        assert_eq!(
+            backend.lookup_mx(&client, "", "relay.dns.podman."),
+            Some(vec![MxRecord {
+                preference: 10,
+                exchange: "relay".to_string(),
+            }])
+        );
+        assert_eq!(backend.lookup_mx(&client, "", "unknown.dns.podman."), None);
+    }
+    #[test]
+    // The search domain lists all containers publishing smtp ordered by preference
+    fn test_lookup_mx_search_domain() {
+        let backend =
+            parse_configs_with_search_domain("src/test/config/podman_services", "dns.podman")
+                .expect("parse config error")
+                .0;
+        let client: IpAddr = "10.88.0.4".parse().unwrap();
+        assert_eq!(
+            backend.lookup_mx(&client, "", "DNS.podman."),
+            Some(vec![
+                MxRecord {
+                    preference: 10,
+                    exchange: "mail2".to_string(),
+                },
+                MxRecord {
+                    preference: 20,
+                    exchange: "mail1".to_string(),
+                }
+            ])
+        );
+        assert_eq!(backend.fqdn("podman", "mail1"), "mail1.dns.podman.");
+    }
+    #[test]
+    // Container metadata must be kept and merged for containers listed on several lines
+    fn test_lookup_containers() {
+        let backend = parse_configs("src/test/config/podman_v6_entries")
+            .expect("parse config error")
+            .0;
+        let res = backend
+            .lookup_containers(&IP_10_89_0_3, "", "test1")
+            .expect("container not found");
+        assert_eq!(res.len(), 1);
+        let (network, ctr) = &res[0];
+        assert_eq!(network, "podman_v6_entries");
+        assert_eq!(
+            ctr.id,
+            "7b46c7ad93fcbcb945c35286a5ba19d6976093e2ce39d2cb38ba1eba636404ab"
+        );
+        assert_eq!(ctr.aliases, vec!["test1", "7b46c7ad93fc"]);
+        assert_eq!(ctr.ips, vec![IP_10_89_0_2, IP_FDFD_733B_DC3_220B_2]);
+
+        assert!(backend
+            .lookup_containers(&IP_10_89_0_3, "", "unknown")
+            .is_none());
+    }
+    #[test]
+    // Aliases must only resolve to a canonical name in CNAME mode and when unambiguous
+    fn test_lookup_canonical() {
+        let options = config::ConfigOptions {
+            cname_aliases: true,
+            ..Default::default()
+        };
+        let backend = config::parse_configs("src/test/config/podman_services", &options)
+            .expect("parse config error")
+            .0;
+        let client: IpAddr = "10.88.0.4".parse().unwrap();
+        assert_eq!(
+            backend.lookup_canonical(&client, "", "webalias"),
+            Some("web".to_string())
+        );
+        assert_eq!(
+            backend.lookup_canonical(&client, "", "RELAY."),
+            Some("mail1".to_string())
+        );
+        // container names are never CNAMEs
+        assert_eq!(backend.lookup_canonical(&client, "", "web2"), None);
+        // web is a container name and an alias of web2
+        assert_eq!(backend.lookup_canonical(&client, "", "web"), None);
+        assert_eq!(backend.lookup_canonical(&client, "", "unknown"), None);
+
+        let backend = parse_configs("src/test/config/podman_services")
+            .expect("parse config error")
+            .0;
+        assert_eq!(backend.lookup_canonical(&client, "", "webalias"), None);
+    }
+    #[test]
+    // The search domain zone and its name server must be known to the backend
+    fn test_search_domain_zone() {
+        let backend =
+            parse_configs_with_search_domain("src/test/config/podman_services", "dns.podman")
+                .expect("parse config error")
+                .0;
+        let client: IpAddr = "10.88.0.4".parse().unwrap();
+        assert!(backend.in_search_domain("podman", "dns.podman."));
+        assert!(backend.in_search_domain("podman", "Web.DNS.podman."));
+        assert!(!backend.in_search_domain("podman", "foodns.podman."));
+        assert!(!backend.in_search_domain("podman", "example.com."));
+
+        assert!(backend.name_exists(&client, "podman", "dns.podman."));
+        assert!(backend.name_exists(&client, "podman", "web.dns.podman."));
+        assert!(backend.name_exists(&client, "podman", "_http._tcp.web.dns.podman."));
+        assert!(!backend.name_exists(&client, "podman", "nothere.dns.podman."));
+
+        // The name server resolves to the listen ip of the network of the request.
+        assert_eq!(
+            backend.lookup(&client, "podman", "ns.dns.podman."),
+            Some(vec!["10.88.0.1".parse().unwrap()])
+        );
+
+        // Without search domain there is no zone.
+        let backend = parse_configs("src/test/config/podman_services")
+            .expect("parse config error")
+            .0;
+        assert!(!backend.in_search_domain("podman", "dns.podman."));
+        assert_eq!(backend.lookup(&client, "podman", "ns"), None);
+    }
+    #[test]
+    // Reverse zones must be derived from the subnets of the network
+    fn test_reverse_zones() {
+        let backend =
+            parse_configs_with_search_domain("src/test/config/podman_v6_entries", "dns.podman")
+                .expect("parse config error")
+                .0;
+        assert_eq!(
+            backend.reverse_zones["podman_v6_entries"],
+            vec![
+                "0.89.10.in-addr.arpa",
+                "b.0.2.2.3.c.d.0.b.3.3.7.d.f.d.f.ip6.arpa"
+            ]
+        );
+        assert_eq!(
+            backend.authoritative_zone("podman_v6_entries", "9.0.89.10.in-addr.arpa."),
+            Some("0.89.10.in-addr.arpa".to_string())
+        );
+        assert_eq!(
+            backend.authoritative_zone("podman_v6_entries", "web.dns.podman."),
+            Some("dns.podman".to_string())
+        );
+        assert_eq!(
+            backend.authoritative_zone("podman_v6_entries", "9.0.88.10.in-addr.arpa."),
+            None
+        );
+
+        // Known addresses exist, unknown addresses in the zone do not.
+        assert!(backend.name_exists(
+            &IP_10_89_0_2,
+            "podman_v6_entries",
+            "3.0.89.10.in-addr.arpa."
+        ));
+        assert!(!backend.name_exists(
+            &IP_10_89_0_2,
+            "podman_v6_entries",
+            "9.0.89.10.in-addr.arpa."
+        ));
+        assert!(backend.name_exists(&IP_10_89_0_2, "podman_v6_entries", "0.89.10.in-addr.arpa."));
+
+        // Without subnets only the known addresses have a zone, the zones of
+        // other networks do not count.
+        let zones = &backend.reverse_zones["podman_v6_entries_proper"];
+        assert!(zones.contains(&"1.0.0.10.in-addr.arpa".to_string()));
+        assert!(zones.contains(&"2.0.0.10.in-addr.arpa".to_string()));
+        assert_eq!(
+            backend.authoritative_zone("podman_v6_entries_proper", "9.0.0.10.in-addr.arpa."),
+            None
+        );
+        assert_eq!(
+            backend.authoritative_zone("podman_v6_entries_proper", "9.0.89.10.in-addr.arpa."),
+            None
+        );
+    }
+    #[test]
+    // Subnets must be covered by reverse zones on octet and nibble boundaries
+    fn test_subnet_reverse_zones() {
+        assert_eq!(
+            backend::subnet_reverse_zones(&"10.88.0.0/16".parse().unwrap()),
+            vec!["88.10.in-addr.arpa"]
+        );
+        let zones = backend::subnet_reverse_zones(&"10.88.16.1/20".parse().unwrap());
+        assert_eq!(zones.len(), 16);
+        assert_eq!(zones[0], "16.88.10.in-addr.arpa");
+        assert_eq!(zones[15], "31.88.10.in-addr.arpa");
+        assert_eq!(
+            backend::subnet_reverse_zones(&"10.88.0.4/30".parse().unwrap()),
+            vec![
+                "4.0.88.10.in-addr.arpa",
+                "5.0.88.10.in-addr.arpa",
+                "6.0.88.10.in-addr.arpa",
+                "7.0.88.10.in-addr.arpa"
+            ]
+        );
+        assert_eq!(
+            backend::subnet_reverse_zones(&"fdfd:733b:dc3:220b::/64".parse().unwrap()),
+            vec!["b.0.2.2.3.c.d.0.b.3.3.7.d.f.d.f.ip6.arpa"]
+        );
+        assert_eq!(
+            backend::subnet_reverse_zones(&"fdfd:733b:dc3:2200::/62".parse().unwrap()),
+            vec![
+                "0.0.2.2.3.c.d.0.b.3.3.7.d.f.d.f.ip6.arpa",
+                "1.0.2.2.3.c.d.0.b.3.3.7.d.f.d.f.ip6.arpa",
+                "2.0.2.2.3.c.d.0.b.3.3.7.d.f.d.f.ip6.arpa",
+                "3.0.2.2.3.c.d.0.b.3.3.7.d.f.d.f.ip6.arpa"
+            ]
+        );
+    }
+    #[test]
+    // Reverse lookup names must be parsed into ip addresses
+    fn test_reverse_name_to_ip() {
+        assert_eq!(
+            backend::reverse_name_to_ip("4.0.88.10.in-addr.arpa."),
+            Some(IP_10_88_0_4)
+        );
+        assert_eq!(
+            backend::reverse_name_to_ip(
+                "2.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.b.0.2.2.3.c.d.0.b.3.3.7.d.f.d.f.IP6.ARPA."
+            ),
+            Some(IP_FDFD_733B_DC3_220B_2)
+        );
+        assert_eq!(backend::reverse_name_to_ip("0.88.10.in-addr.arpa."), None);
+        assert_eq!(backend::reverse_name_to_ip("example.com."), None);
+    }
+    #[test]
+    // Wildcard names must only match names without a more specific entry
+    fn test_lookup_wildcard() {
+        let backend =
+            parse_configs_with_search_domain("src/test/config/podman_wildcard", "dns.podman")
+                .expect("parse config error")
+                .0;
+        let client: IpAddr = "10.88.0.5".parse().unwrap();
+        let wildcard: IpAddr = "10.88.0.2".parse().unwrap();
+
+        // Exact names take priority.
+        assert_eq!(
+            backend.lookup(&client, "", "api.tenant"),
+            Some(vec!["10.88.0.3".parse().unwrap()])
+        );
+        assert_eq!(backend.lookup(&client, "", "tenant"), Some(vec![wildcard]));
+        // Names below the wildcard match at any depth.
+        assert_eq!(
+            backend.lookup(&client, "", "foo.tenant"),
+            Some(vec![wildcard])
+        );
+        assert_eq!(
+            backend.lookup(&client, "", "FOO.bar.tenant.dns.podman."),
+            Some(vec![wildcard])
+        );
+        // Names below an existing name are not covered by the wildcard.
+        assert_eq!(backend.lookup(&client, "", "foo.web.tenant"), None);
+        assert_eq!(backend.lookup(&client, "", "foo.client"), None);
+        assert_eq!(backend.lookup(&client, "", "foo.other"), None);
+        // Names with entries below them exist without records.
+        assert_eq!(backend.lookup(&client, "", "deep.tenant"), None);
+        assert!(backend.name_exists(&client, "podman", "deep.tenant.dns.podman."));
+        assert_eq!(backend.lookup(&client, "", "foo.deep.tenant"), None);
+        assert!(!backend.name_exists(&client, "podman", "foo.deep.tenant.dns.podman."));
+        // The wildcard itself can be queried.
+        assert_eq!(
+            backend.lookup(&client, "", "*.tenant"),
+            Some(vec![wildcard])
+        );
+
+        // Wildcards in other positions are rejected.
+        let backend = parse_configs("src/test/config/podman_bad_wildcard")
+            .expect("parse config error")
+            .0;
+        assert!(!backend.name_mappings.contains_key("podman"));
+    }
+    #[test]
+    // Answers must be ordered by the answer order of the network
+    fn test_answer_order() {
+        let backend = parse_configs("src/test/config/podman_answer_order")
+            .expect("parse config error")
+            .0;
+        assert_eq!(
+            backend.network_answer_order.get("podman"),
+            Some(&AnswerOrder::RoundRobin)
+        );
+        assert_eq!(backend.network_answer_order.get("podman2"), None);
+        // Invalid options are ignored, the rest of the network is loaded.
+        assert_eq!(backend.network_answer_order.get("podman_bad"), None);
+        assert!(backend.name_mappings["podman_bad"].contains_key("rep1"));
+
+        // Round-robin rotates by one address per answer.
+        let mut expected: Vec<IpAddr> = vec![
+            "10.88.0.2".parse().unwrap(),
+            "10.88.0.3".parse().unwrap(),
+            "10.88.0.4".parse().unwrap(),
+        ];
+        for _ in 0..4 {
+            let mut addrs = backend
+                .lookup(&"10.88.0.2".parse().unwrap(), "podman", "rep")
+                .unwrap();
+            backend.order_answers("podman", "rep/A", &mut addrs);
+            assert_eq!(addrs, expected);
+            expected.rotate_left(1);
+        }
+
+        // The default order is stable.
+        let stable: Vec<IpAddr> = vec!["10.89.0.2".parse().unwrap(), "10.89.0.3".parse().unwrap()];
+        for _ in 0..3 {
+            let mut addrs = stable.clone();
+            backend.order_answers("podman2", "rep/A", &mut addrs);
+            assert_eq!(addrs, stable);
+        }
+
+        // Shuffling keeps all addresses.
+        let mut backend = backend;
+        backend.answer_order = AnswerOrder::Shuffle;
+        let mut addrs = stable.clone();
+        backend.order_answers("podman2", "rep/A", &mut addrs);
+        addrs.sort();
+        assert_eq!(addrs, stable);
+    }
+    #[test]
+    // The TTL of local answers must be set globally and per network
+    fn test_ttl() {
+        let backend = parse_configs("src/test/config/podman_ttl")
+            .expect("parse config error")
+            .0;
+        assert_eq!(backend.ttl("podman"), 30);
+        assert_eq!(backend.ttl("podman2"), 0);
+        // Invalid options are ignored, the rest of the network is loaded.
+        assert_eq!(backend.ttl("podman_bad"), 0);
+        assert_eq!(
+            backend.network_answer_order.get("podman_bad"),
+            Some(&AnswerOrder::Shuffle)
+        );
+        assert!(backend.name_mappings["podman_bad"].contains_key("app"));
+
+        let options = config::ConfigOptions {
+            ttl: 5,
+            ..Default::default()
+        };
+        let backend = config::parse_configs("src/test/config/podman_ttl", &options)
+            .expect("parse config error")
+            .0;
+        assert_eq!(backend.ttl("podman"), 30);
+        assert_eq!(backend.ttl("podman2"), 5);
+    }
+    #[test]
+    // Gateway names must resolve to the listen ips of the network the request was made on
+    fn test_lookup_gateway_names() {
+        let options = config::ConfigOptions {
+            search_domains: vec!["dns.podman".to_string()],
+            gateway_names: vec![
+                "host.containers.internal".to_string(),
+                "Gateway.".to_string(),
+                "rep1".to_string(),
+            ],
+            ..Default::default()
+        };
+        let backend = config::parse_configs("src/test/config/podman_answer_order", &options)
+            .expect("parse config error")
+            .0;
+        let client: IpAddr = "10.88.0.2".parse().unwrap();
+        assert_eq!(
+            backend.lookup(&client, "podman", "host.containers.internal."),
+            Some(vec!["10.88.0.1".parse().unwrap()])
+        );
+        assert_eq!(
+            backend.lookup(&client, "podman", "gateway.dns.podman."),
+            Some(vec!["10.88.0.1".parse().unwrap()])
+        );
+        assert_eq!(
+            backend.lookup(&client, "podman2", "GATEWAY"),
+            Some(vec!["10.89.0.1".parse().unwrap()])
+        );
+        // Container names take priority.
+        assert_eq!(
+            backend.lookup(&client, "podman", "rep1"),
+            Some(vec!["10.88.0.2".parse().unwrap()])
+        );
+    }
+    #[test]
+    // Gateway names are opt-in, none are resolved with the default options
+    fn test_lookup_gateway_names_default() {
+        let backend = config::parse_configs(
+            "src/test/config/podman_answer_order",
+            &config::ConfigOptions::default(),
+        )
+        .expect("parse config error")
+        .0;
+        let client: IpAddr = "10.88.0.2".parse().unwrap();
+        assert!(backend.gateway_names.is_empty());
+        for name in ["host.containers.internal.", "gateway."] {
+            assert_eq!(backend.lookup(&client, "podman", name), None);
+        }
+    }
+    #[test]
+    // Names qualified with a network must only resolve on that network
+    fn test_lookup_network_qualified() {
+        let backend = parse_configs_with_search_domain(
+            "src/test/config/podman_network_qualified",
+            "dns.podman",
+        )
+        .expect("parse config error")
+        .0;
+        let app: IpAddr = "10.88.0.2".parse().unwrap();
+        let web: IpAddr = "10.88.0.4".parse().unwrap();
+
+        let mut all = backend.lookup(&app, "frontend", "db").unwrap();
+        all.sort();
+        assert_eq!(
+            all,
+            vec![
+                "10.88.0.3".parse::<IpAddr>().unwrap(),
+                "10.89.0.3".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            backend.lookup(&app, "frontend", "db.backend.dns.podman."),
+            Some(vec!["10.89.0.3".parse().unwrap()])
+        );
+        assert_eq!(
+            backend.lookup(&app, "frontend", "DB.Frontend"),
+            Some(vec!["10.88.0.3".parse().unwrap()])
+        );
+        assert_eq!(backend.lookup(&app, "frontend", "web.backend"), None);
+        // The requester must be a member of the network.
+        assert_eq!(backend.lookup(&web, "frontend", "db.backend"), None);
+        assert!(!backend.name_exists(&web, "frontend", "db.backend.dns.podman."));
+    }
+    #[test]
+    // Containers must resolve by full id and unique id prefixes of the requester networks
+    fn test_lookup_container_id() {
+        let backend = parse_configs_with_search_domain("src/test/config/podman_ids", "dns.podman")
+            .expect("parse config error")
+            .0;
+        let web: IpAddr = "10.88.0.2".parse().unwrap();
+        let client: IpAddr = "10.88.0.4".parse().unwrap();
+
+        assert_eq!(
+            backend.lookup(
+                &web,
+                "podman",
+                "8bcc5fe0cb09bee5dfb71d61503a87688cfc82aa5f130bcedb19357a17765926"
+            ),
+            Some(vec![client, "fdfd::4".parse().unwrap()])
+        );
+        assert_eq!(
+            backend.lookup(&web, "podman", "8BCC5FE0CB09.dns.podman."),
+            Some(vec![client, "fdfd::4".parse().unwrap()])
+        );
+        // The client is on both networks, all its addresses are returned.
+        assert_eq!(
+            backend.lookup(&client, "podman", "8bcc5fe0cb09"),
+            Some(vec![
+                client,
+                "fdfd::4".parse().unwrap(),
+                "10.89.0.4".parse().unwrap()
+            ])
+        );
+        assert_eq!(
+            backend.lookup(&client, "podman", "8bcc5fe0cb09.podman2"),
+            Some(vec!["10.89.0.4".parse().unwrap()])
+        );
+        // Ambiguous and too short prefixes are not resolved.
+        assert_eq!(backend.lookup(&client, "podman", "68fb291b0318"), None);
+        assert_eq!(
+            backend.lookup(&client, "podman", "68fb291b0318b"),
+            Some(vec![web])
+        );
+        assert_eq!(backend.lookup(&client, "podman", "8bcc5fe0cb0"), None);
+        // Containers on other networks are not visible.
+        assert_eq!(
+            backend.lookup(&client, "podman", "e5df0cdbe013"),
+            Some(vec!["10.89.0.2".parse().unwrap()])
+        );
+        assert_eq!(backend.lookup(&web, "podman", "e5df0cdbe013"), None);
+
+        assert_eq!(
+            backend.reverse_lookup_id(&web, &client),
+            Some("8bcc5fe0cb09".to_string())
+        );
+        assert_eq!(
+            backend.reverse_lookup_id(&web, &"10.89.0.2".parse().unwrap()),
+            None
+        );
+    }
+    #[test]
+    // The short id must only be answered in reverse lookups when enabled
+    fn test_ptr_short_id_option() {
+        let backend = parse_configs_with_search_domain("src/test/config/podman_ids", "dns.podman")
+            .expect("parse config error")
+            .0;
+        assert!(!backend.ptr_short_id);
+
+        let backend = config::parse_configs(
+            "src/test/config/podman_ids",
+            &config::ConfigOptions {
+                search_domains: vec!["dns.podman".to_string()],
+                ptr_short_id: true,
+                ..Default::default()
+            },
+        )
+        .expect("parse config error")
+        .0;
+        assert!(backend.ptr_short_id);
+        let web: IpAddr = "10.88.0.2".parse().unwrap();
+        let client: IpAddr = "10.88.0.4".parse().unwrap();
+        assert_eq!(
+            backend.reverse_lookup_id(&client, &web),
+            Some("68fb291b0318".to_string())
+        );
+    }
+    #[test]
+    // Group names must resolve to all members on the requester networks
+    fn test_lookup_group() {
+        let backend =
+            parse_configs_with_search_domain("src/test/config/podman_groups", "dns.podman")
+                .expect("parse config error")
+                .0;
+        let client: IpAddr = "10.88.0.4".parse().unwrap();
+        let other: IpAddr = "10.89.0.4".parse().unwrap();
+
+        assert_eq!(
+            backend.group_mappings["podman"]["shop"],
+            vec![
+                "68fb291b0318b54a71f6f3636e58bd0896f084e5ba4fa311ecf36e019c5e6e43",
+                "95655fb6832ba134efa66e9c80862a6c9b04f3cc6abf8adfdda8c38112c2c6fa"
+            ]
+        );
+        assert_eq!(
+            backend.lookup(&client, "podman", "shop.dns.podman."),
+            Some(vec![
+                "10.88.0.2".parse().unwrap(),
+                "10.88.0.3".parse().unwrap(),
+                "fdfd::3".parse().unwrap()
+            ])
+        );
+        assert_eq!(
+            backend.lookup(&client, "podman", "backend"),
+            Some(vec![
+                "10.88.0.3".parse().unwrap(),
+                "fdfd::3".parse().unwrap()
+            ])
+        );
+        // Only members on the shared networks are returned.
+        assert_eq!(
+            backend.lookup(&other, "podman2", "shop"),
+            Some(vec!["10.89.0.3".parse().unwrap()])
+        );
+        assert_eq!(backend.lookup(&other, "podman2", "backend"), None);
+        assert_eq!(
+            backend.lookup(&client, "podman", "shop.podman"),
+            Some(vec![
+                "10.88.0.2".parse().unwrap(),
+                "10.88.0.3".parse().unwrap(),
+                "fdfd::3".parse().unwrap()
+            ])
+        );
+        // Group names are not returned for services.
+        assert_eq!(
+            backend.lookup_srv(&client, "podman", "_http._tcp.shop"),
+            None
+        );
+    }
+    #[test]
+    // Networks must use their own search domains or the global ones
+    fn test_search_domains() {
+        let options = config::ConfigOptions {
+            search_domains: vec![".dns.podman".to_string(), "podman.local".to_string()],
+            ..Default::default()
+        };
+        let backend = config::parse_configs("src/test/config/podman_search_domains", &options)
+            .expect("parse config error")
+            .0;
+        let web: IpAddr = "10.88.0.2".parse().unwrap();
+        let api: IpAddr = "10.89.0.2".parse().unwrap();
+        let db: IpAddr = "10.90.0.2".parse().unwrap();
+
+        assert_eq!(backend.search_domains, vec!["dns.podman", "podman.local"]);
+        assert_eq!(
+            backend.search_domains("staging"),
+            vec!["svc.internal", "staging.internal"]
+        );
+        assert!(backend.search_domains("isolated").is_empty());
+
+        assert_eq!(
+            backend.lookup(&web, "dev", "web.dns.podman."),
+            Some(vec![web])
+        );
+        assert_eq!(
+            backend.lookup(&web, "dev", "web.podman.local."),
+            Some(vec![web])
+        );
+        assert_eq!(backend.lookup(&web, "dev", "web.svc.internal."), None);
+        assert_eq!(
+            backend.lookup(&api, "staging", "api.svc.internal."),
+            Some(vec![api])
+        );
+        assert_eq!(
+            backend.lookup(&api, "staging", "api.staging.internal."),
+            Some(vec![api])
+        );
+        assert_eq!(backend.lookup(&api, "staging", "api.dns.podman."), None);
+        assert_eq!(backend.lookup(&db, "isolated", "db"), Some(vec![db]));
+        assert_eq!(backend.lookup(&db, "isolated", "db.dns.podman."), None);
+
+        assert_eq!(
+            backend.authoritative_zone("staging", "nothere.svc.internal."),
+            Some("svc.internal".to_string())
+        );
+        assert_eq!(
+            backend.authoritative_zone("dev", "nothere.svc.internal."),
+            None
+        );
+        assert!(backend.is_search_domain("dev", "podman.local."));
+        assert!(!backend.in_search_domain("isolated", "db.dns.podman."));
+
+        assert_eq!(backend.fqdn("dev", "web"), "web.dns.podman.");
+        assert_eq!(backend.fqdn("staging", "api"), "api.svc.internal.");
+        assert_eq!(backend.fqdn("isolated", "db"), "db.");
+    }
+    #[test]
+    // Reverse lookups must return the unique names of all shared networks
+    fn test_reverse_lookup_shared_networks() {
+        let backend = parse_configs("src/test/config/podman_reverse")
+            .expect("parse config error")
+            .0;
+        let client: IpAddr = "10.88.0.4".parse().unwrap();
+        let web: IpAddr = "10.88.0.2".parse().unwrap();
+
+        let mut names = backend.reverse_lookup(&client, &web).unwrap();
+        assert_eq!(names[0], "web");
+        names.sort();
+        assert_eq!(names, vec!["api", "frontend", "web", "www"]);
+        assert_eq!(
+            backend.reverse_lookup(&"10.88.0.9".parse().unwrap(), &web),
+            None
+        );
+
+        // Without ptr_all_aliases only the names on the first shared network.
+        let first = backend.reverse_lookup_first(&client, &web).unwrap();
+        assert!(
+            first == vec!["web", "www", "api"] || first == vec!["web", "frontend"],
+            "unexpected names {:?}",
+            first
+        );
+        assert_eq!(
+            backend.reverse_lookup_first(&"10.88.0.9".parse().unwrap(), &web),
+            None
+        );
+    }
+    #[test]
+    // Static records must be visible like container names
+    fn test_static_records() {
+        let backend =
+            parse_configs_with_search_domain("src/test/config/podman_records", "dns.podman")
+                .expect("parse config error")
+                .0;
+        let client: IpAddr = "10.88.0.4".parse().unwrap();
+        let other: IpAddr = "10.89.0.2".parse().unwrap();
+
+        assert_eq!(
+            backend.lookup(&client, "podman", "license.dns.podman."),
+            Some(vec![
+                "192.168.1.10".parse().unwrap(),
+                "fd00::10".parse().unwrap()
+            ])
+        );
+        assert_eq!(
+            backend.lookup_cname(&client, "podman", "mockapi.dns.podman."),
+            Some("web.dns.podman.".to_string())
+        );
+        assert_eq!(
+            backend.lookup_cname(&client, "podman", "registry"),
+            Some("registry.example.com.".to_string())
+        );
+        assert_eq!(
+            backend.lookup_txt(&client, "podman", "license"),
+            Some(vec!["key=abc def".to_string()])
+        );
+        assert_eq!(
+            backend.lookup_txt(&client, "podman", "web"),
+            Some(vec!["owner=team-a".to_string()])
+        );
+        assert!(backend.name_exists(&client, "podman", "mockapi.dns.podman."));
+
+        // Records of other networks are not visible.
+        assert_eq!(backend.lookup(&client, "podman", "hidden"), None);
+        assert_eq!(backend.lookup_cname(&other, "podman2", "mockapi"), None);
+        assert_eq!(backend.lookup_txt(&other, "podman2", "license"), None);
+        assert_eq!(
+            backend.lookup(&other, "podman2", "hidden"),
+            Some(vec!["192.168.1.20".parse().unwrap()])
+        );
+
+        // A CNAME must not have other records and only known types are allowed.
+        assert!(!backend.name_mappings.contains_key("podman_conflict"));
+        assert!(!backend.name_mappings.contains_key("podman_bad_type"));
+    }
+
+    #[test]
+    fn test_zone_files() {
+        use aardvark_dns::backend::zone::ZoneAnswer;
+        use hickory_proto::rr::{Name, RData, RecordType};
+
+        let backend =
+            parse_configs_with_search_domain("src/test/config/podman_zones", "dns.podman")
+                .expect("parse config error")
+                .0;
+        let name = |n: &str| Name::from_ascii(n).unwrap();
+        let zone = backend
+            .lookup_zone("podman", &name("WWW.Example.com."))
+            .expect("zone example.com");
+        assert_eq!(zone.origin, name("example.com."));
+
+        // CNAME chains are followed within the zone.
+        match zone.lookup(&name("www.example.com."), RecordType::A) {
+            ZoneAnswer::Answer(answers, additionals) => {
+                let data: Vec<RData> = answers.into_iter().map(|r| r.data).collect();
+                assert_eq!(
+                    data,
+                    vec![
+                        RData::CNAME(hickory_proto::rr::rdata::CNAME(name("web.example.com."))),
+                        RData::CNAME(hickory_proto::rr::rdata::CNAME(name("app.example.com."))),
+                        RData::A("192.168.100.80".parse().unwrap()),
+                    ]
+                );
+                assert!(additionals.is_empty());
+            }
+            a => panic!("unexpected answer {:?}", a),
+        }
+
+        // Addresses of MX targets are added as additional records.
+        match zone.lookup(&name("example.com."), RecordType::MX) {
+            ZoneAnswer::Answer(answers, additionals) => {
+                assert_eq!(answers.len(), 1);
+                assert_eq!(additionals.len(), 2);
+                assert_eq!(answers[0].ttl, 300);
+            }
+            a => panic!("unexpected answer {:?}", a),
+        }
+
+        // Wildcards only match when no closer name exists.
+        match zone.lookup(&name("a.b.apps.example.com."), RecordType::A) {
+            ZoneAnswer::Answer(answers, _) => {
+                assert_eq!(answers[0].name, name("a.b.apps.example.com."));
+                assert_eq!(answers[0].data, RData::A("192.168.100.90".parse().unwrap()));
+            }
+            a => panic!("unexpected answer {:?}", a),
+        }
+        assert_eq!(
+            zone.lookup(&name("x.b.c.example.com."), RecordType::A),
+            ZoneAnswer::NxDomain
+        );
+
+        // Empty non-terminals and other types are NODATA.
+        assert_eq!(
+            zone.lookup(&name("c.example.com."), RecordType::A),
+            ZoneAnswer::NoData
+        );
+        assert_eq!(
+            zone.lookup(&name("app.example.com."), RecordType::AAAA),
+            ZoneAnswer::NoData
+        );
+        assert_eq!(
+            zone.lookup(&name("missing.example.com."), RecordType::A),
+            ZoneAnswer::NxDomain
+        );
+        // The negative caching TTL is the SOA minimum.
+        assert_eq!(zone.negative_soa().unwrap().ttl, 60);
+
+        // Zones are only served to their network.
+        assert!(backend
+            .lookup_zone("podman2", &name("www.example.com."))
+            .is_none());
+        assert!(backend
+            .lookup_zone("podman2", &name("www.example.org."))
+            .is_some());
+
+        // Zones without SOA or overlapping the search domain are skipped.
+        assert!(backend
+            .lookup_zone("podman", &name("www.nosoa.test."))
+            .is_none());
+        assert!(backend
+            .lookup_zone("podman", &name("web.dns.podman."))
+            .is_none());
+        assert_eq!(backend.zones.get("podman").map(|z| z.len()), Some(1));
+        assert_eq!(
+            backend.lookup(&IP_10_88_0_2, "podman", "web.dns.podman."),
+            Some(vec![IP_10_88_0_2])
+        );
+
+        // A zone overlapping the reverse zones of the network is skipped, the
+        // reverse zones of other networks do not matter.
+        assert!(backend
+            .lookup_zone("podman", &name("9.0.88.10.in-addr.arpa."))
+            .is_none());
+        assert!(backend
+            .lookup_zone("podman2", &name("9.0.88.10.in-addr.arpa."))
+            .is_some());
+    }
+
+    #[test]
+    // A network named zones does not collide with the directory of the zone
+    // files
+    fn test_network_named_zones() {
+        use hickory_proto::rr::Name;
+
+        let conf =
+            parse_configs("src/test/config/podman_network_zones").expect("parse config error");
+        assert!(conf.1.contains_key("zones"));
+        assert_eq!(
+            conf.0.lookup(&IP_10_88_0_4, "zones", "web"),
+            Some(vec![IP_10_88_0_2])
+        );
+        assert!(conf
+            .0
+            .lookup_zone("zones", &Name::from_ascii("www.example.org.").unwrap())
+            .is_some());
+    }
+    #[test]
+    fn test_zone_transfer() {
+        let mut backend =
+            parse_configs_with_search_domain("src/test/config/podman_records", "dns.podman")
+                .expect("parse config error")
+                .0;
+        let client: IpAddr = "10.88.0.4".parse().unwrap();
+        let host: IpAddr = "10.89.0.1".parse().unwrap();
+
+        // Names of the requester's networks including static records and the
+        // name server, but not the names of other networks.
+        assert_eq!(
+            backend.zone_names(&client, "podman", "dns.podman"),
+            vec!["client", "license", "mockapi", "ns", "registry", "web"]
+        );
+        assert_eq!(
+            backend.zone_names(&host, "podman2", "dns.podman"),
+            vec!["hidden", "ns", "other"]
+        );
+
+        // Only the host may transfer zones unless allowed by the ACL.
+        assert!(backend.transfer_allowed(&host));
+        assert!(backend.transfer_allowed(&"127.0.0.1".parse().unwrap()));
+        assert!(!backend.transfer_allowed(&client));
+        backend.transfer_acl = vec!["10.88.0.0/28".parse().unwrap()];
+        assert!(backend.transfer_allowed(&client));
+        assert!(!backend.transfer_allowed(&"10.88.0.20".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_dynamic_update() {
+        use hickory_proto::op::ResponseCode;
+        use hickory_proto::rr::rdata::{CNAME, NS, TXT};
+        use hickory_proto::rr::{DNSClass, Name, RData, Record, RecordType};
+
+        let mut backend =
+            parse_configs_with_search_domain("src/test/config/podman_records", "dns.podman")
+                .expect("parse config error")
+                .0;
+        let client: IpAddr = "10.88.0.4".parse().unwrap();
+        let name = |n: &str| Name::from_ascii(n).unwrap();
+        let add = |n: &str, data: RData| Record::from_rdata(name(n), 60, data);
+        let delete = |n: &str, class: DNSClass, record_type: RecordType| {
+            let mut record = Record::update0(name(n), 0, record_type);
+            record.dns_class = class;
+            record
+        };
+        let a = |ip: &str| RData::A(ip.parse::<Ipv4Addr>().unwrap().into());
+
+        // Add addresses and a TXT record to a new name.
+        let updates = vec![
+            add("db.dns.podman.", a("10.88.0.50")),
+            add("db.dns.podman.", a("10.88.0.51")),
+            add(
+                "db.dns.podman.",
+                RData::TXT(TXT::new(vec!["role=primary".into()])),
+            ),
+        ];
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[], &updates),
+            ResponseCode::NoError
+        );
+        assert_eq!(
+            backend.lookup(&client, "podman", "db"),
+            Some(vec![
+                "10.88.0.50".parse().unwrap(),
+                "10.88.0.51".parse().unwrap()
+            ])
+        );
+        assert_eq!(
+            backend.lookup_txt(&client, "podman", "db.dns.podman."),
+            Some(vec!["role=primary".to_string()])
+        );
+        // The records are answered with the TTL of the update.
+        assert_eq!(
+            backend.record_ttl(&client, "podman", "db.dns.podman.", RecordType::A),
+            60
+        );
+        assert_eq!(
+            backend.record_ttl(&client, "podman", "db", RecordType::TXT),
+            60
+        );
+        assert_eq!(
+            backend.record_ttl(&client, "podman", "db", RecordType::AAAA),
+            backend.ttl("podman")
+        );
+        assert!(backend
+            .zone_names(&client, "podman", "dns.podman")
+            .contains(&"db".to_string()));
+        // Dynamic names are only visible on the network they were added to.
+        assert_eq!(
+            backend.lookup(&"10.89.0.2".parse().unwrap(), "podman2", "db"),
+            None
+        );
+
+        // Prerequisites: the name must (not) exist, RRsets must match.
+        let prerequisite = delete("db.dns.podman.", DNSClass::NONE, RecordType::ANY);
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[prerequisite], &[]),
+            ResponseCode::YXDomain
+        );
+        let mut prerequisite = add("db.dns.podman.", a("10.88.0.50"));
+        prerequisite.ttl = 0;
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[prerequisite], &[]),
+            ResponseCode::NXRRSet
+        );
+        let prerequisite = delete("cache.dns.podman.", DNSClass::ANY, RecordType::A);
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[prerequisite], &[]),
+            ResponseCode::NXRRSet
+        );
+
+        // Delete a single address, then the whole name.
+        let mut update = add("db.dns.podman.", a("10.88.0.50"));
+        update.dns_class = DNSClass::NONE;
+        update.ttl = 0;
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[], &[update]),
+            ResponseCode::NoError
+        );
+        assert_eq!(
+            backend.lookup(&client, "podman", "db"),
+            Some(vec!["10.88.0.51".parse().unwrap()])
+        );
+        let update = delete("db.dns.podman.", DNSClass::ANY, RecordType::ANY);
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[], &[update]),
+            ResponseCode::NoError
+        );
+        assert_eq!(backend.lookup(&client, "podman", "db"), None);
+
+        // A CNAME can not be added to a name with other records.
+        let updates = vec![
+            add("api.dns.podman.", a("10.88.0.60")),
+            add(
+                "api.dns.podman.",
+                RData::CNAME(CNAME(name("web.dns.podman."))),
+            ),
+        ];
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[], &updates),
+            ResponseCode::NoError
+        );
+        assert_eq!(backend.lookup_cname(&client, "podman", "api"), None);
+        let updates = vec![
+            delete("api.dns.podman.", DNSClass::ANY, RecordType::ANY),
+            add(
+                "api.dns.podman.",
+                RData::CNAME(CNAME(name("web.dns.podman."))),
+            ),
+        ];
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[], &updates),
+            ResponseCode::NoError
+        );
+        assert_eq!(
+            backend.lookup_cname(&client, "podman", "api"),
+            Some("web.dns.podman.".to_string())
+        );
+
+        // Names of containers and static records can not be changed, the
+        // whole update is refused.
+        for update in [
+            add("web.dns.podman.", a("10.88.0.60")),
+            add(
+                "web.dns.podman.",
+                RData::TXT(TXT::new(vec!["owner=team-b".into()])),
+            ),
+            add(
+                "license.dns.podman.",
+                RData::CNAME(CNAME(name("web.dns.podman."))),
+            ),
+            add("mockapi.dns.podman.", a("10.88.0.60")),
+            delete("web.dns.podman.", DNSClass::ANY, RecordType::ANY),
+        ] {
+            let updates = vec![add("db.dns.podman.", a("10.88.0.50")), update];
+            assert_eq!(
+                backend.apply_update(&client, "podman", "dns.podman", &[], &updates),
+                ResponseCode::Refused
+            );
+        }
+        assert_eq!(
+            backend.lookup(&client, "podman", "web"),
+            Some(vec![IP_10_88_0_2])
+        );
+        assert_eq!(
+            backend.lookup_txt(&client, "podman", "web.dns.podman."),
+            Some(vec!["owner=team-a".to_string()])
+        );
+        assert_eq!(backend.lookup_cname(&client, "podman", "license"), None);
+        assert_eq!(backend.lookup(&client, "podman", "db"), None);
+
+        // Invalid updates change nothing.
+        let updates = vec![
+            add("cache.dns.podman.", a("10.88.0.70")),
+            add("example.com.", a("10.88.0.70")),
+        ];
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[], &updates),
+            ResponseCode::NotZone
+        );
+        let updates = vec![add("dns.podman.", a("10.88.0.70"))];
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[], &updates),
+            ResponseCode::Refused
+        );
+        let updates = vec![
+            add(
+                "cache.dns.podman.",
+                RData::CNAME(CNAME(name("web.dns.podman."))),
+            ),
+            add("cache.dns.podman.", RData::NS(NS(name("ns.dns.podman.")))),
+        ];
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[], &updates),
+            ResponseCode::NotImp
+        );
+        assert_eq!(backend.lookup_cname(&client, "podman", "cache"), None);
+
+        // Updates exceeding the limits of the network are refused as a whole.
+        let updates: Vec<Record> = (0..1001)
+            .map(|i| add(&format!("host{i}.dns.podman."), a("10.88.0.80")))
+            .collect();
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[], &updates),
+            ResponseCode::Refused
+        );
+        assert_eq!(backend.lookup(&client, "podman", "host0"), None);
+        // The network already has the name api.
+        assert_eq!(
+            backend.apply_update(&client, "podman", "dns.podman", &[], &updates[..999]),
+            ResponseCode::NoError
+        );
+
+        // Only the host may send unsigned updates unless allowed by the ACL.
+        assert!(backend.update_allowed(&"10.88.0.1".parse().unwrap()));
+        assert!(!backend.update_allowed(&client));
+        backend.update_acl = vec!["10.88.0.4/32".parse().unwrap()];
+        assert!(backend.update_allowed(&client));
+    }
+    #[test]
+    // Dynamic names belong to the zone they were added to, not to every search
+    // domain of the network
+    fn test_dynamic_update_zones() {
+        use hickory_proto::op::ResponseCode;
+        use hickory_proto::rr::{Name, RData, Record};
+
+        let backend = parse_configs("src/test/config/podman_search_domains")
+            .expect("parse config error")
+            .0;
+        let client: IpAddr = "10.89.0.2".parse().unwrap();
+        let update = Record::from_rdata(
+            Name::from_ascii("leader.svc.internal.").unwrap(),
+            30,
+            RData::A("10.89.0.100".parse::<Ipv4Addr>().unwrap().into()),
+        );
+        assert_eq!(
+            backend.apply_update(&client, "staging", "svc.internal", &[], &[update]),
+            ResponseCode::NoError
+        );
+
+        let leader = Some(vec!["10.89.0.100".parse().unwrap()]);
+        assert_eq!(
+            backend.lookup(&client, "staging", "leader.svc.internal."),
+            leader
+        );
+        // Names without search domain are in the first one.
+        assert_eq!(backend.lookup(&client, "staging", "leader"), leader);
+        assert_eq!(
+            backend.lookup(&client, "staging", "leader.staging.internal."),
+            None
+        );
+        assert_eq!(
+            backend.zone_names(&client, "staging", "svc.internal"),
+            vec!["api", "leader", "ns"]
+        );
+        assert_eq!(
+            backend.zone_names(&client, "staging", "staging.internal"),
+            vec!["api", "ns"]
+        );
+    }
 }
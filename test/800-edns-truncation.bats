@@ -0,0 +1,53 @@
+#!/usr/bin/env bats   -*- bats -*-
+#
+# EDNS and truncation tests
+#
+
+load helpers
+
+# Creates container aone with enough long aliases that the TXT record with
+# its metadata does not fit into a 512 byte UDP response.
+function create_big_container() {
+	subnet_a=$(random_subnet 5)
+	aliases=$(for i in $(seq 1 30); do printf '"alias-with-a-rather-long-name-%02d",' $i; done)
+	create_config network_name="podman1" container_id=$(random_string 64) container_name="aone" subnet="$subnet_a" aliases="${aliases%,}"
+	a1_config="$config"
+	gw=$(echo "$a1_config" | jq -r .network_info.podman1.subnets[0].gateway)
+	create_container "$a1_config"
+	a1_pid=$CONTAINER_NS_PID
+}
+
+@test "udp response without edns is truncated at 512 bytes" {
+	create_big_container
+
+	run_in_container_netns "$a1_pid" "dig" "+noedns" "+ignore" "TXT" "aone" "@$gw"
+	assert "$output" =~ "flags: qr aa tc rd ra;" "truncated response has the TC bit"
+	assert "$output" =~ "ANSWER: 0," "truncated response has no answers"
+
+	# dig retries truncated responses over tcp by default
+	run_in_container_netns "$a1_pid" "dig" "+noedns" "TXT" "aone" "@$gw"
+	assert "$output" =~ "flags: qr aa rd ra;" "tcp response is not truncated"
+	assert "$output" =~ "alias-with-a-rather-long-name-30" "tcp response has the full answer"
+}
+
+@test "udp response uses the edns payload size" {
+	create_big_container
+
+	run_in_container_netns "$a1_pid" "dig" "+bufsize=1232" "+ignore" "TXT" "aone" "@$gw"
+	assert "$output" =~ "flags: qr aa rd ra;" "response within the payload size is not truncated"
+	assert "$output" =~ "alias-with-a-rather-long-name-30" "response has the full answer"
+	assert "$output" =~ "EDNS: version: 0, flags:; udp: 1232" "response has our payload size"
+
+	# payload sizes below 512 are treated as 512
+	run_in_container_netns "$a1_pid" "dig" "+bufsize=100" "+ignore" "TXT" "aone" "@$gw"
+	assert "$output" =~ "flags: qr aa tc rd ra;" "response larger than 512 bytes is truncated"
+	assert "$output" =~ "EDNS: version: 0, flags:; udp: 1232" "truncated response keeps the OPT record"
+
+	# the DO bit of the client is echoed
+	run_in_container_netns "$a1_pid" "dig" "+dnssec" "aone" "@$gw"
+	assert "$output" =~ "EDNS: version: 0, flags: do; udp: 1232" "response echoes the DO bit"
+
+	# unsupported EDNS versions are answered with BADVERS
+	run_in_container_netns "$a1_pid" "dig" "+edns=1" "+noednsnegotiation" "aone" "@$gw"
+	assert "$output" =~ "status: BADVERS" "response to EDNS version 1"
+}
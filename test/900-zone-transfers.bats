@@ -0,0 +1,59 @@
+#!/usr/bin/env bats   -*- bats -*-
+#
+# zone transfer tests
+#
+
+load helpers
+
+@test "zone transfer of the search domain" {
+	subnet_a=$(random_subnet 5)
+	create_config network_name="podman1" container_id=$(random_string 64) container_name="aone" subnet="$subnet_a" aliases='"a1"'
+	a1_config="$config"
+	a1_ip=$(echo "$a1_config" | jq -r .networks.podman1.static_ips[0])
+	gw=$(echo "$a1_config" | jq -r .network_info.podman1.subnets[0].gateway)
+	create_container "$a1_config"
+	a1_pid=$CONTAINER_NS_PID
+
+	# the host may transfer the zone, it starts and ends with the SOA
+	run_in_host_netns "dig" "+noall" "+answer" "axfr" "dns.podman" "@$gw"
+	assert "$(head -n1 <<<"$output")" =~ "^dns\.podman\.[[:space:]]+0[[:space:]]+IN[[:space:]]+SOA" "first record is the SOA"
+	assert "$(tail -n1 <<<"$output")" =~ "^dns\.podman\.[[:space:]]+0[[:space:]]+IN[[:space:]]+SOA" "last record is the SOA"
+	assert "$output" =~ "aone\.dns\.podman\.[[:space:]]+0[[:space:]]+IN[[:space:]]+A[[:space:]]+$a1_ip"
+	assert "$output" =~ "a1\.dns\.podman\.[[:space:]]+0[[:space:]]+IN[[:space:]]+A[[:space:]]+$a1_ip"
+	assert "$output" =~ "aone\.dns\.podman\.[[:space:]]+0[[:space:]]+IN[[:space:]]+TXT"
+
+	# containers are refused
+	run_in_container_netns "$a1_pid" "dig" "axfr" "dns.podman" "@$gw"
+	assert "$output" =~ "Transfer failed" "transfer to a container is refused"
+
+	# names which are not a zone can not be transferred
+	run_in_host_netns "dig" "axfr" "aone.dns.podman" "@$gw"
+	assert "$output" =~ "Transfer failed" "transfer of a name is refused"
+}
+
+@test "zone transfer of a zone file" {
+	subnet_a=$(random_subnet 5)
+	create_config network_name="podman1" container_id=$(random_string 64) container_name="aone" subnet="$subnet_a"
+	a1_config="$config"
+	gw=$(echo "$a1_config" | jq -r .network_info.podman1.subnets[0].gateway)
+
+	# the zone needs more than one message of 16KiB
+	zones_dir="$AARDVARK_TMPDIR/aardvark-dns/_zones/podman1"
+	mkdir -p "$zones_dir"
+	{
+		echo '$TTL 300'
+		echo '@ IN SOA ns.example.com. hostmaster.example.com. 1 3600 600 86400 60'
+		echo 'www IN A 192.168.100.80'
+		for i in $(seq 1 500); do
+			echo "txt$i IN TXT \"some text which makes the zone large enough\""
+		done
+	} >"$zones_dir/example.com.zone"
+
+	create_container "$a1_config"
+
+	run_in_host_netns "dig" "axfr" "example.com" "@$gw"
+	assert "$output" =~ "example\.com\.[[:space:]]+300[[:space:]]+IN[[:space:]]+SOA"
+	assert "$output" =~ "www\.example\.com\.[[:space:]]+300[[:space:]]+IN[[:space:]]+A[[:space:]]+192\.168\.100\.80"
+	assert "$output" =~ "txt500\.example\.com\.[[:space:]]+300[[:space:]]+IN[[:space:]]+TXT"
+	assert "$output" =~ "XFR size: 503 records \(messages [2-9]," "transfer is split into several messages"
+}